@@ -0,0 +1,111 @@
+use super::{
+    cell::CellWidth,
+    command::{Command, Commands, ParseMode},
+    config::{Config, EofPolicy, OverflowPolicy},
+    error::InterpreterError,
+    interpreter::Interpreter,
+    optimizer::{OptLevel, Pass},
+    source::SourceMap,
+    tape::TapePolicy,
+};
+use std::time::Duration;
+
+// Builder of the configuration shared by the tokenizer and the interpreter
+#[derive(Clone, Debug, Default)]
+pub struct InterpreterBuilder {
+    config: Config,
+    mode: ParseMode,
+    source: Option<SourceMap>,
+}
+
+impl InterpreterBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse_mode(mut self, mode: ParseMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn eof(mut self, eof: EofPolicy) -> Self {
+        self.config.eof = eof;
+        self
+    }
+
+    pub fn tape_size(mut self, tape_size: usize) -> Self {
+        self.config.tape_size = tape_size;
+        self
+    }
+
+    pub fn tape(mut self, tape: TapePolicy) -> Self {
+        self.config.tape = tape;
+        self
+    }
+
+    pub fn cell(mut self, cell: CellWidth) -> Self {
+        self.config.cell = cell;
+        self
+    }
+
+    pub fn overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.config.overflow = overflow;
+        self
+    }
+
+    pub fn max_steps(mut self, max_steps: u64) -> Self {
+        self.config.max_steps = Some(max_steps);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    pub fn opt_level(mut self, level: OptLevel) -> Self {
+        self.config.passes = level.passes();
+        self
+    }
+
+    pub fn pass(mut self, pass: Pass, enabled: bool) -> Self {
+        self.config.set_pass(pass, enabled);
+        self
+    }
+
+    pub fn jit(mut self, enabled: bool) -> Self {
+        self.config.jit = enabled;
+        self
+    }
+
+    pub fn debug_dump(mut self, enabled: bool) -> Self {
+        self.config.debug_dump = enabled;
+        self
+    }
+
+    // The errors of `compile` and of the interpreter show their line and column in `source`
+    pub fn source(mut self, source: SourceMap) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    // Generate the tokens of `code` for the configured interpreter
+    pub fn compile(&self, code: &str) -> Result<Commands, InterpreterError> {
+        Command::code_to_tokens(code.to_string(), self.mode, &self.config).map_err(|error| {
+            match &self.source {
+                Some(source) => source.locate(error),
+                None => error,
+            }
+        })
+    }
+
+    pub fn build(self) -> Interpreter {
+        let mut interpreter = Interpreter::new(self.config);
+        interpreter.set_source(self.source);
+        interpreter
+    }
+}
@@ -0,0 +1,437 @@
+use super::{
+    cell::CellWidth,
+    command::{BufferOptions, Command, LoopOptions},
+    config::{Config, EofPolicy, OverflowPolicy},
+    error::InterpreterError,
+    interpreter::DUMP_RADIUS,
+    tape::TapePolicy,
+};
+use std::fmt::Write;
+
+const HEADER: &str = r#"/* Generated by brainfuck emit-c */
+#include <inttypes.h>
+#include <stdarg.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#define TAPE_WRAP 0
+#define TAPE_ERROR 1
+#define TAPE_GROW_RIGHT 2
+#define TAPE_GROW_BOTH 3
+
+#define EOF_UNCHANGED 0
+#define EOF_ZERO 1
+#define EOF_MAX 2
+#define EOF_ERROR 3
+#define EOF_ZERO_THEN_ERROR 4
+"#;
+
+// Same behaviour as `Interpreter`, the errors print the messages of `InterpreterError`
+const RUNTIME: &str = r##"
+static cell_t *tape;
+static size_t len = TAPE_SIZE, ptr = 0, origin = 0;
+static int input_ended = 0;
+
+/* The output written so far is kept */
+static inline void fail(const char *format, ...) {
+    va_list args;
+    fflush(stdout);
+    va_start(args, format);
+    vfprintf(stderr, format, args);
+    va_end(args);
+    fputc('\n', stderr);
+    exit(1);
+}
+
+/* Cell number as seen by the program, negative when the tape has grown to the left */
+static inline int64_t position(void) {
+    return (int64_t)ptr - (int64_t)origin;
+}
+
+/* Cell number of the cell at `offset` from the pointer, the cyclic tape wraps it */
+static inline int64_t position_at(int64_t offset) {
+#if TAPE_POLICY == TAPE_WRAP
+    int64_t cell = (position() + offset) % (int64_t)len;
+    return cell < 0 ? cell + (int64_t)len : cell;
+#else
+    return position() + offset;
+#endif
+}
+
+static inline void overflow(int64_t index_file, int64_t cell) {
+    fail("The value of memory cell `%" PRId64 "` has overflowed at code position `%" PRId64 "`", cell, index_file);
+}
+
+static inline void infinite(int64_t index_file) {
+    fail("An infinite loop has been found, at code position `%" PRId64 "`, with a current value `%" PRIu64 "` in memory cell `%" PRId64 "`",
+         index_file, (uint64_t)tape[ptr], position());
+}
+
+static inline cell_t *allocate(size_t size) {
+    cell_t *cells = calloc(size, sizeof *cells);
+    if (cells == NULL) {
+        fail("Execution Error: Out of memory");
+    }
+    return cells;
+}
+
+/* Index of the cell at `offset` from the pointer, following the tape policy */
+static inline size_t at(int64_t offset, int64_t index_file) {
+    int64_t target = (int64_t)ptr + offset;
+    if (target >= 0 && target < (int64_t)len) {
+        return (size_t)target;
+    }
+#if TAPE_POLICY == TAPE_WRAP
+    (void)index_file;
+    target %= (int64_t)len;
+    return (size_t)(target < 0 ? target + (int64_t)len : target);
+#else
+#if TAPE_POLICY == TAPE_GROW_RIGHT || TAPE_POLICY == TAPE_GROW_BOTH
+    if (target >= (int64_t)len) {
+        /* Doubling keeps the cost of growing constant on average */
+        size_t new_len = (size_t)target + 1 > len * 2 ? (size_t)target + 1 : len * 2;
+        cell_t *cells = realloc(tape, new_len * sizeof *cells);
+        if (cells == NULL) {
+            fail("Execution Error: Out of memory");
+        }
+        memset(cells + len, 0, (new_len - len) * sizeof *cells);
+        tape = cells;
+        len = new_len;
+        return (size_t)target;
+    }
+#endif
+#if TAPE_POLICY == TAPE_GROW_BOTH
+    (void)index_file;
+    {
+        size_t missing = (size_t)-target > len ? (size_t)-target : len;
+        cell_t *cells = allocate(len + missing);
+        memcpy(cells + missing, tape, len * sizeof *cells);
+        free(tape);
+        tape = cells;
+        len += missing;
+        ptr += missing;
+        origin += missing;
+        return missing - (size_t)-target;
+    }
+#else
+    /* The pointer leaves from the last cell in the direction of the move */
+    fail("The pointer has left the tape at code position `%" PRId64 "`, from memory cell `%" PRId64 "`", index_file,
+         (target < 0 ? 0 : (int64_t)len - 1) - (int64_t)origin);
+    return 0;
+#endif
+#endif
+}
+
+/* If the cell at `offset` is the current cell */
+static inline int aliased(int64_t offset) {
+#if TAPE_POLICY == TAPE_WRAP
+    return offset % (int64_t)len == 0;
+#else
+    return offset == 0;
+#endif
+}
+
+static inline void store(size_t cell, int64_t offset, int64_t value, int64_t index_file) {
+#if CHECKED
+    if (value < 0 || value > (int64_t)CELL_MAX) {
+        overflow(index_file, position_at(offset));
+    }
+#else
+    (void)offset;
+    (void)index_file;
+#endif
+    tape[cell] = (cell_t)value;
+}
+
+static inline void add(int64_t offset, int64_t value, int64_t index_file) {
+    size_t cell = at(offset, index_file);
+#if CHECKED
+    store(cell, offset, (int64_t)tape[cell] + value, index_file);
+#else
+    store(cell, offset, (int64_t)(cell_t)(tape[cell] + (cell_t)value), index_file);
+#endif
+}
+
+static inline void set(int64_t offset, int64_t value, int64_t index_file) {
+    store(at(offset, index_file), offset, value, index_file);
+}
+
+static inline void move(int64_t offset, int64_t index_file) {
+    ptr = at(offset, index_file);
+}
+
+static inline void input(int64_t offset, int64_t index_file) {
+    size_t cell = at(offset, index_file);
+    int byte;
+    /* The program may be waiting for the user, show the pending output */
+    fflush(stdout);
+    byte = input_ended ? EOF : getchar();
+    if (byte != EOF) {
+        tape[cell] = (cell_t)byte;
+        return;
+    }
+#if EOF_POLICY == EOF_ZERO
+    tape[cell] = 0;
+#elif EOF_POLICY == EOF_MAX
+    tape[cell] = CELL_MAX;
+#elif EOF_POLICY == EOF_ERROR
+    fail("Missing arguments");
+#elif EOF_POLICY == EOF_ZERO_THEN_ERROR
+    if (input_ended) {
+        fail("Missing arguments");
+    }
+    tape[cell] = 0;
+    input_ended = 1;
+#endif
+}
+
+static inline void output(int64_t offset, int64_t index_file) {
+    /* Growing the tape moves it, the index is found before reading it */
+    size_t cell = at(offset, index_file);
+    putchar((unsigned char)tape[cell]);
+}
+
+/* `#`: the cells around the pointer are printed to stderr, the current one in brackets */
+static inline void dump(int64_t index_file) {
+    size_t first = ptr > DUMP_RADIUS ? ptr - DUMP_RADIUS : 0;
+    size_t last = len - 1 - ptr > DUMP_RADIUS ? ptr + DUMP_RADIUS : len - 1;
+    fprintf(stderr, "# code position `%" PRId64 "`, memory cell `%" PRId64 "`:", index_file, position());
+    for (size_t cell = first; cell <= last; cell++) {
+        fprintf(stderr, cell == ptr ? " [%" PRId64 ":%" PRIu64 "]" : " %" PRId64 ":%" PRIu64,
+                (int64_t)cell - (int64_t)origin, (uint64_t)tape[cell]);
+    }
+    fputc('\n', stderr);
+}
+
+static inline void comment(int64_t index_file) {
+    if (tape[ptr]) {
+        infinite(index_file);
+    }
+}
+
+/* Times `step` has to be added to `value` to reach 0 modulo 2^bits, 0 if it never does */
+static inline int steps_to_zero(cell_t value, cell_t step, uint64_t *steps) {
+    unsigned shift = 0;
+    uint64_t odd, inverse;
+    int round;
+    if (value == 0) {
+        *steps = 0;
+        return 1;
+    }
+    if (step == 0) {
+        return 0;
+    }
+    while (!((step >> shift) & 1)) {
+        shift++;
+    }
+    if (value & (((uint64_t)1 << shift) - 1)) {
+        return 0;
+    }
+    /* The odd part of the step has an inverse, Newton's iteration doubles its correct bits each round */
+    odd = (uint64_t)(step >> shift);
+    inverse = odd;
+    for (round = 0; round < 5; round++) {
+        inverse *= 2 - odd * inverse;
+    }
+    *steps = ((uint64_t)(cell_t)-value >> shift) * inverse & ((uint64_t)CELL_MAX >> shift);
+    return 1;
+}
+
+static inline void add_to_reset(int64_t step, int64_t index_file) {
+    if (!tape[ptr]) {
+        return;
+    }
+#if CHECKED
+    /* Only decreasing by a divisor of the value, otherwise 0 is skipped */
+    if (!(step < 0 && (int64_t)tape[ptr] % step == 0)) {
+        overflow(index_file, position());
+    }
+#else
+    {
+        uint64_t steps;
+        if (!steps_to_zero(tape[ptr], (cell_t)step, &steps)) {
+            infinite(index_file);
+        }
+    }
+#endif
+    tape[ptr] = 0;
+}
+
+static inline uint64_t gcd(uint64_t a, uint64_t b) {
+    return b == 0 ? a : gcd(b, a % b);
+}
+
+static inline void scan_zero(int64_t stride, int64_t index_file) {
+#if TAPE_POLICY == TAPE_WRAP
+    size_t start = ptr;
+    while (tape[ptr]) {
+        ptr = at(stride, index_file);
+        if (ptr == start) {
+            /* Every cell is visited when the stride and the length are coprime */
+            if (gcd((uint64_t)(stride < 0 ? -stride : stride), len) == 1) {
+                fail("An infinite movement loop has been found, at code position `%" PRId64 "`, memory does not contain value `0`", index_file);
+            }
+            fail("An infinite movement loop has been found, at code position `%" PRId64 "`, the pointer never stops moving, memory cell movement is `%" PRId64 "`",
+                 index_file, stride);
+        }
+    }
+#else
+    while (tape[ptr]) {
+        ptr = at(stride, index_file);
+    }
+#endif
+}
+
+/* Loop whose body adds `factors[i]` to the cell at `offsets[i]` until the current cell is 0 */
+static inline void add_until_zero(const int64_t *offsets, const int64_t *factors, size_t count, int64_t index_file) {
+    int64_t step = 0;
+    int unchanged = 1;
+    size_t i;
+    if (!tape[ptr]) {
+        return;
+    }
+    for (i = 0; i < count; i++) {
+        if (aliased(offsets[i])) {
+            step += factors[i];
+        } else if (factors[i]) {
+            unchanged = 0;
+        }
+    }
+#if CHECKED
+    /* Iterating finds which cell overflows first, bounded cells always overflow or reach 0 unless no cell changes */
+    if (step == 0 && unchanged) {
+        infinite(index_file);
+    }
+    while (tape[ptr]) {
+        for (i = 0; i < count; i++) {
+            add(offsets[i], factors[i], index_file);
+        }
+    }
+#else
+    {
+        uint64_t steps;
+        (void)unchanged;
+        /* The cells are checked before the loop runs, growing the tape could move them */
+        for (i = 0; i < count; i++) {
+            at(offsets[i], index_file);
+        }
+        /* The loop runs `steps` times, the first solution of `value + steps * step = 0` */
+        if (!steps_to_zero(tape[ptr], (cell_t)step, &steps)) {
+            infinite(index_file);
+        }
+        for (i = 0; i < count; i++) {
+            if (!aliased(offsets[i])) {
+                size_t cell = at(offsets[i], index_file);
+                tape[cell] = (cell_t)(tape[cell] + (cell_t)((uint64_t)factors[i] * steps));
+            }
+        }
+        tape[ptr] = 0;
+    }
+#endif
+}
+"##;
+
+// C source of a standalone program that runs `tokens` with the tape, cells and EOF policy of `config`.
+// The step and time limits are not included.
+pub fn emit_c(tokens: &[Command], config: &Config) -> Result<String, InterpreterError> {
+    let (cell_type, cell_max) = match config.cell {
+        CellWidth::Bits8 => ("uint8_t", "UINT8_MAX"),
+        CellWidth::Bits16 => ("uint16_t", "UINT16_MAX"),
+        CellWidth::Bits32 => ("uint32_t", "UINT32_MAX"),
+        // C has no integer without limits
+        CellWidth::Unbounded => {
+            return Err(InterpreterError::InvalidOption(
+                "--cell".to_string(),
+                "bignum".to_string(),
+            ))
+        }
+    };
+    let tape = match config.tape {
+        TapePolicy::Wrap => "TAPE_WRAP",
+        TapePolicy::Error => "TAPE_ERROR",
+        TapePolicy::GrowRight => "TAPE_GROW_RIGHT",
+        TapePolicy::GrowBoth => "TAPE_GROW_BOTH",
+    };
+    let eof = match config.eof {
+        EofPolicy::Unchanged => "EOF_UNCHANGED",
+        EofPolicy::Zero => "EOF_ZERO",
+        EofPolicy::MaxValue => "EOF_MAX",
+        EofPolicy::Error => "EOF_ERROR",
+        EofPolicy::ZeroThenError => "EOF_ZERO_THEN_ERROR",
+    };
+
+    let mut code = String::from(HEADER);
+    writeln!(code, "\ntypedef {} cell_t;", cell_type).unwrap();
+    writeln!(code, "#define CELL_MAX {}", cell_max).unwrap();
+    writeln!(code, "#define TAPE_SIZE {}", config.tape_size.max(1)).unwrap();
+    writeln!(code, "#define TAPE_POLICY {}", tape).unwrap();
+    writeln!(code, "#define EOF_POLICY {}", eof).unwrap();
+    let checked = config.overflow == OverflowPolicy::Error;
+    writeln!(code, "#define CHECKED {}", checked as u8).unwrap();
+    writeln!(code, "#define DUMP_RADIUS {}", DUMP_RADIUS).unwrap();
+    code.push_str(RUNTIME);
+    code.push_str("\nint main(void) {\n    tape = allocate(len);\n");
+
+    let mut depth = 1usize;
+    for token in tokens {
+        if let Command::Loop(LoopOptions::PointerEnd(_), _) = token {
+            depth -= 1;
+        }
+        let line = match token {
+            Command::Add(value, offset, i) => format!("add({}, {}, {});", offset, value, i),
+            Command::Set(value, offset, i) => format!("set({}, {}, {});", offset, value, i),
+            Command::Move(offset, i) => format!("move({}, {});", offset, i),
+            Command::Buffer(BufferOptions::Input, offset, i) => {
+                format!("input({}, {});", offset, i)
+            }
+            Command::Buffer(BufferOptions::Output, offset, i) => {
+                format!("output({}, {});", offset, i)
+            }
+            Command::Dump(i) => format!("dump({});", i),
+            Command::Loop(LoopOptions::Comment, i) => format!("comment({});", i),
+            Command::Loop(LoopOptions::AddToReset(step), i) => {
+                format!("add_to_reset({}, {});", step, i)
+            }
+            Command::Loop(LoopOptions::MoveToCell(stride), i) => {
+                format!("scan_zero({}, {});", stride, i)
+            }
+            Command::Loop(LoopOptions::CutAdd(offset, value_1, value_2), i) => {
+                add_until_zero(&[(0, *value_1), (*offset, *value_2)], *i)
+            }
+            Command::Loop(LoopOptions::MultiplyAdd(updates), i) => {
+                add_until_zero(&[&[(0, -1)], updates.as_slice()].concat(), *i)
+            }
+            Command::Loop(LoopOptions::PointerStart(Some(_)), _) => {
+                "while (tape[ptr]) {".to_string()
+            }
+            Command::Loop(LoopOptions::PointerEnd(Some(_)), _) => "}".to_string(),
+            Command::Loop(LoopOptions::PointerStart(None) | LoopOptions::PointerEnd(None), _) => {
+                return Err(InterpreterError::UnconnectedLoops)
+            }
+        };
+        writeln!(code, "{:width$}{}", "", line, width = depth * 4).unwrap();
+        if let Command::Loop(LoopOptions::PointerStart(_), _) = token {
+            depth += 1;
+        }
+    }
+
+    code.push_str("    fflush(stdout);\n    return 0;\n}\n");
+    Ok(code)
+}
+
+// Call of the runtime for a loop that only adds, the current cell is the first update
+fn add_until_zero(updates: &[(isize, i64)], index_file: usize) -> String {
+    let (offsets, factors): (Vec<String>, Vec<String>) = updates
+        .iter()
+        .map(|(offset, factor)| (offset.to_string(), factor.to_string()))
+        .unzip();
+    format!(
+        "add_until_zero((const int64_t[]){{{}}}, (const int64_t[]){{{}}}, {}, {});",
+        offsets.join(", "),
+        factors.join(", "),
+        updates.len(),
+        index_file
+    )
+}
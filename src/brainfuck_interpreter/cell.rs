@@ -0,0 +1,273 @@
+use num_bigint::{BigInt, Sign};
+use std::{fmt::Display, str::FromStr};
+
+use super::error::InterpreterError;
+
+// Size of the memory cells
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CellWidth {
+    #[default]
+    Bits8,
+    Bits16,
+    Bits32,
+    Unbounded, // signed integer without limits
+}
+
+impl CellWidth {
+    pub fn bits(&self) -> Option<u32> {
+        match self {
+            Self::Bits8 => Some(8),
+            Self::Bits16 => Some(16),
+            Self::Bits32 => Some(32),
+            Self::Unbounded => None,
+        }
+    }
+
+    // Equivalent increment in the range [-2^(bits-1), 2^(bits-1)), e.g. 255 is -1 in 8 bits
+    pub fn normalize(&self, value: i64) -> i64 {
+        match self.bits() {
+            Some(bits) => {
+                let half = 1i64 << (bits - 1);
+                (value + half).rem_euclid(half * 2) - half
+            }
+            None => value,
+        }
+    }
+
+    // If the value is inside the limits of the cell
+    pub fn contains(&self, value: i64) -> bool {
+        match self.bits() {
+            Some(bits) => (0..1i64 << bits).contains(&value),
+            None => true,
+        }
+    }
+
+    // If adding `step` repeatedly to a cell with `value` reaches the value 0
+    pub fn reaches_zero(&self, value: i64, step: i64) -> bool {
+        match self {
+            Self::Bits8 => 0u8.add(value).reaches_zero(step),
+            Self::Bits16 => 0u16.add(value).reaches_zero(step),
+            Self::Bits32 => 0u32.add(value).reaches_zero(step),
+            Self::Unbounded => BigInt::from(value).reaches_zero(step),
+        }
+    }
+
+    // How a cell with `value` is shown
+    pub fn display(&self, value: i64) -> String {
+        match self {
+            Self::Bits8 => 0u8.add(value).to_string(),
+            Self::Bits16 => 0u16.add(value).to_string(),
+            Self::Bits32 => 0u32.add(value).to_string(),
+            Self::Unbounded => value.to_string(),
+        }
+    }
+}
+
+impl FromStr for CellWidth {
+    type Err = InterpreterError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "8" => Ok(Self::Bits8),
+            "16" => Ok(Self::Bits16),
+            "32" => Ok(Self::Bits32),
+            "bignum" => Ok(Self::Unbounded),
+            _ => Err(InterpreterError::InvalidOption(
+                "--cell".to_string(),
+                name.to_string(),
+            )),
+        }
+    }
+}
+
+// Value stored in a memory cell.
+// Input bytes are stored as 0 to 255 and the output is the value modulo 256 (the lowest byte).
+pub trait Cell: Clone + Default + PartialEq + Display {
+    // If the values have a minimum and a maximum
+    const BOUNDED: bool;
+
+    fn is_zero(&self) -> bool;
+    // Increment with wraparound
+    fn add(&self, value: i64) -> Self;
+    // Increment, `None` on overflow or underflow
+    fn checked_add(&self, value: i64) -> Option<Self>;
+    // Increment by `factor * multiplier` with wraparound
+    fn multiply_add(&self, factor: i64, multiplier: &Self) -> Self;
+    // Increment by `factor * multiplier`, `None` if the result overflows
+    fn checked_multiply_add(&self, factor: i64, multiplier: &Self) -> Option<Self>;
+    fn from_byte(byte: u8) -> Self;
+    fn to_byte(&self) -> u8;
+    // Value as an integer without limits
+    fn to_bigint(&self) -> BigInt;
+    // Value of `-1`: all bits set, or `-1` for unbounded cells
+    fn max_value() -> Self;
+    // If adding `step` repeatedly reaches the value 0
+    fn reaches_zero(&self, step: i64) -> bool;
+    // Times `step` has to be added to reach the value 0, `None` if it never does
+    fn steps_to_zero(&self, step: i64) -> Option<Self>;
+    // If adding `step` repeatedly reaches the value 0 without overflow
+    fn checked_reaches_zero(&self, step: i64) -> bool;
+    // Index of the first cell with 0
+    fn find_zero(cells: &[Self]) -> Option<usize> {
+        cells.iter().position(Self::is_zero)
+    }
+    // Index of the last cell with 0
+    fn rfind_zero(cells: &[Self]) -> Option<usize> {
+        cells.iter().rposition(Self::is_zero)
+    }
+}
+
+macro_rules! impl_cell {
+    ($type:ty $(, $method:item)*) => {
+        impl Cell for $type {
+            const BOUNDED: bool = true;
+
+            fn is_zero(&self) -> bool {
+                *self == 0
+            }
+
+            fn add(&self, value: i64) -> Self {
+                // Truncating the increment keeps its value modulo 2^bits
+                self.wrapping_add(value as $type)
+            }
+
+            fn checked_add(&self, value: i64) -> Option<Self> {
+                i64::from(*self)
+                    .checked_add(value)
+                    .and_then(|result| <$type>::try_from(result).ok())
+            }
+
+            fn multiply_add(&self, factor: i64, multiplier: &Self) -> Self {
+                self.wrapping_add((factor as $type).wrapping_mul(*multiplier))
+            }
+
+            fn checked_multiply_add(&self, factor: i64, multiplier: &Self) -> Option<Self> {
+                // Adding `factor` once per unit of `multiplier` only moves in one direction,
+                // it overflows if and only if the final value does
+                let result = i128::from(*self) + i128::from(factor) * i128::from(*multiplier);
+                <$type>::try_from(result).ok()
+            }
+
+            fn from_byte(byte: u8) -> Self {
+                byte.into()
+            }
+
+            fn to_bigint(&self) -> BigInt {
+                BigInt::from(*self)
+            }
+
+            fn to_byte(&self) -> u8 {
+                *self as u8
+            }
+
+            fn max_value() -> Self {
+                <$type>::MAX
+            }
+
+            fn reaches_zero(&self, step: i64) -> bool {
+                // `value + n * step = 0 (mod 2^bits)` has solution when gcd(step, 2^bits) divides `value`
+                (step as $type) != 0 && (step as $type).trailing_zeros() <= self.trailing_zeros()
+            }
+
+            fn steps_to_zero(&self, step: i64) -> Option<Self> {
+                if !self.reaches_zero(step) {
+                    return None;
+                }
+                // Solve `value + n * step = 0 (mod 2^bits)`: removing the common powers of 2 leaves an odd step,
+                // which has an inverse modulo 2^(bits - shift)
+                let shift = (step as $type).trailing_zeros();
+                let step_odd = u64::from((step as $type) >> shift);
+                let value = u64::from(*self >> shift);
+                // Newton's iteration, each round doubles the correct bits of the inverse (3, 6, 12, 24, 48, 96)
+                let mut inverse = step_odd;
+                for _ in 0..5 {
+                    inverse =
+                        inverse.wrapping_mul(2u64.wrapping_sub(step_odd.wrapping_mul(inverse)));
+                }
+                let mask = (1u64 << (<$type>::BITS - shift)) - 1;
+                Some((value.wrapping_neg().wrapping_mul(inverse) & mask) as $type)
+            }
+
+            fn checked_reaches_zero(&self, step: i64) -> bool {
+                // Only decreasing by a divisor of the value, otherwise 0 is skipped
+                *self == 0 || (step < 0 && i64::from(*self) % step == 0)
+            }
+
+            $($method)*
+        }
+    };
+}
+
+// memchr compares several bytes at a time
+impl_cell!(
+    u8,
+    fn find_zero(cells: &[Self]) -> Option<usize> {
+        memchr::memchr(0, cells)
+    },
+    fn rfind_zero(cells: &[Self]) -> Option<usize> {
+        memchr::memrchr(0, cells)
+    }
+);
+impl_cell!(u16);
+impl_cell!(u32);
+
+impl Cell for BigInt {
+    const BOUNDED: bool = false;
+
+    fn is_zero(&self) -> bool {
+        self.sign() == Sign::NoSign
+    }
+
+    fn add(&self, value: i64) -> Self {
+        self + value
+    }
+
+    fn checked_add(&self, value: i64) -> Option<Self> {
+        Some(self + value)
+    }
+
+    fn multiply_add(&self, factor: i64, multiplier: &Self) -> Self {
+        self + multiplier * factor
+    }
+
+    fn checked_multiply_add(&self, factor: i64, multiplier: &Self) -> Option<Self> {
+        Some(self.multiply_add(factor, multiplier))
+    }
+
+    fn from_byte(byte: u8) -> Self {
+        byte.into()
+    }
+
+    fn to_byte(&self) -> u8 {
+        // Lowest byte in two's complement, the value modulo 256
+        self.to_signed_bytes_le()[0]
+    }
+
+    fn to_bigint(&self) -> BigInt {
+        self.clone()
+    }
+
+    fn max_value() -> Self {
+        BigInt::from(-1)
+    }
+
+    fn reaches_zero(&self, step: i64) -> bool {
+        // Only by moving towards 0 with a step that divides the value
+        self.is_zero()
+            || (step != 0 && (self % step).is_zero() && (self.sign() == Sign::Minus) != (step < 0))
+    }
+
+    fn steps_to_zero(&self, step: i64) -> Option<Self> {
+        if !self.reaches_zero(step) {
+            None
+        } else if self.is_zero() {
+            Some(BigInt::default())
+        } else {
+            Some(-(self / step))
+        }
+    }
+
+    fn checked_reaches_zero(&self, step: i64) -> bool {
+        self.reaches_zero(step)
+    }
+}
@@ -1,127 +1,256 @@
-use super::error::InterpreterError;
+use super::{
+    config::{Config, OverflowPolicy},
+    error::InterpreterError,
+    optimizer::{optimize, PassStats},
+    tape::TapePolicy,
+};
+use std::collections::HashMap;
 
 pub type Commands = Vec<Command>;
 
-enum CommandClassic {
-    Increase,
-    Decrease,
-    Left,
-    Right,
-    Input,
-    Output,
+// How characters outside the 8 Brainfuck instructions are treated
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ParseMode {
+    Strict, // unknown characters are an error
+    #[default]
+    Lenient, // unknown characters are comments
+}
+
+pub(super) enum CommandClassic {
+    Increase(usize),   // index_file
+    Decrease(usize),   // index_file
+    Left(usize),       // index_file
+    Right(usize),      // index_file
+    Input(usize),      // index_file
+    Output(usize),     // index_file
     OpenLoop(usize),   // index_file
     ClosedLoop(usize), // index_file
+    Dump(usize),       // index_file
 }
 
 impl CommandClassic {
-    pub fn code_to_tokens(code: String) -> Result<Vec<CommandClassic>, InterpreterError> {
+    pub fn code_to_tokens(
+        code: String,
+        mode: ParseMode,
+        dump: bool,
+    ) -> Result<Vec<CommandClassic>, InterpreterError> {
         let mut tokens = Vec::with_capacity(code.len());
-        for (index_file, c) in code.chars().enumerate() {
+        // A loop at the start never runs, in strict mode it can hold any character as a comment
+        let start = match mode {
+            ParseMode::Strict => Self::leading_comment_end(&code),
+            ParseMode::Lenient => 0,
+        };
+        for (index_file, c) in code.chars().enumerate().skip(start) {
             // Match each character to its corresponding Brainfuck command
             match c {
-                '+' => tokens.push(Self::Increase),
-                '-' => tokens.push(Self::Decrease),
-                '<' => tokens.push(Self::Left),
-                '>' => tokens.push(Self::Right),
-                ',' => tokens.push(Self::Input),
-                '.' => tokens.push(Self::Output),
+                '+' => tokens.push(Self::Increase(index_file + 1)),
+                '-' => tokens.push(Self::Decrease(index_file + 1)),
+                '<' => tokens.push(Self::Left(index_file + 1)),
+                '>' => tokens.push(Self::Right(index_file + 1)),
+                ',' => tokens.push(Self::Input(index_file + 1)),
+                '.' => tokens.push(Self::Output(index_file + 1)),
                 '[' => tokens.push(Self::OpenLoop(index_file + 1)),
                 ']' => tokens.push(Self::ClosedLoop(index_file + 1)),
-                char => return Err(InterpreterError::InstruccionUnknown(char, index_file + 1)),
+                '#' if dump => tokens.push(Self::Dump(index_file + 1)),
+                char => match mode {
+                    ParseMode::Strict => {
+                        return Err(InterpreterError::InstruccionUnknown(char, index_file + 1))
+                    }
+                    ParseMode::Lenient => continue, // comment, the position of the next commands is preserved
+                },
             }
         }
 
         Ok(tokens)
     }
+
+    // Characters up to the `]` that closes a `[` at the start of the code, 0 without that loop
+    fn leading_comment_end(code: &str) -> usize {
+        if !code.starts_with('[') {
+            return 0;
+        }
+
+        let mut depth = 0usize;
+        for (index, c) in code.chars().enumerate() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                _ => continue,
+            }
+            if depth == 0 {
+                return index + 1;
+            }
+        }
+
+        0 // without its `]` the loop is parsed as code to report the error
+    }
+
+    pub fn index_file(&self) -> usize {
+        match self {
+            Self::Increase(index_file)
+            | Self::Decrease(index_file)
+            | Self::Left(index_file)
+            | Self::Right(index_file)
+            | Self::Input(index_file)
+            | Self::Output(index_file)
+            | Self::OpenLoop(index_file)
+            | Self::ClosedLoop(index_file)
+            | Self::Dump(index_file) => *index_file,
+        }
+    }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum BufferOptions {
     Input,
     Output,
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum LoopOptions {
-    Comment,                     // [msg]: unimplemented
-    AddToReset(u8),              // [n]: cell to 0
-    MoveToCell(u16),             // [Move(n)]: pointer to cell with 0
-    CutAdd(u16, u8, u8), // [n Move(x) m Move(-x)] | [Move(x) m Move(-x) n]: current cell to 0 and cell in position to (current_value/n)*m
-    PointerStart(Option<usize>), // if a connection exists with the PointerEnd
-    PointerEnd(Option<usize>), // if a connection exists with the PointerStart
+    Comment,                        // []: an infinite loop unless the cell is 0
+    AddToReset(i64),                // [n]: cell to 0
+    MoveToCell(isize),              // [Move(n)]: pointer to cell with 0
+    CutAdd(isize, i64, i64), // [n Add(m, x)] | [Add(m, x) n]: adds n to the current cell and m to the cell in position x until the current cell is 0
+    MultiplyAdd(Vec<(isize, i64)>), // [-1 and adds at offsets]: each cell in offset += factor * current cell, current cell to 0
+    PointerStart(Option<usize>),    // if a connection exists with the PointerEnd
+    PointerEnd(Option<usize>),      // if a connection exists with the PointerStart
 }
 
 // Enum to represent the Brainfuck language commands
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum Command {
-    Add(u8),
-    Move(u16),
-    Buffer(BufferOptions),
+    Add(i64, isize, usize), // increment normalized to the cell width, offset from the pointer, index_file of the first add
+    Set(i64, isize, usize), // value (added to 0 following the overflow policy), offset from the pointer, index_file
+    Move(isize, usize), // offset, index_file of the first move; only at the end of a basic block
+    Buffer(BufferOptions, isize, usize), // input/output, offset from the pointer, index_file
     Loop(LoopOptions, usize), // loop function, index_file
+    Dump(usize),        // `#`: prints the cells around the pointer to stderr, index_file
 }
 
 impl Command {
     // Generate tokens from Brainfuck code
-    pub fn code_to_tokens(code: String) -> Result<Commands, InterpreterError> {
-        let mut tokens: Commands = CommandClassic::code_to_tokens(code)?
+    pub fn code_to_tokens(
+        code: String,
+        mode: ParseMode,
+        config: &Config,
+    ) -> Result<Commands, InterpreterError> {
+        Ok(Self::code_to_tokens_with_stats(code, mode, config)?.0)
+    }
+
+    // Generate tokens from Brainfuck code and report the instructions removed by each optimization pass
+    pub fn code_to_tokens_with_stats(
+        code: String,
+        mode: ParseMode,
+        config: &Config,
+    ) -> Result<(Commands, Vec<PassStats>), InterpreterError> {
+        let tokens: Commands = CommandClassic::code_to_tokens(code, mode, config.debug_dump)?
             .iter()
             .map(|command| match command {
-                CommandClassic::Increase => Self::Add(1),
-                CommandClassic::Decrease => Self::Add(u8::MAX),
-                CommandClassic::Left => Self::Move(u16::MAX),
-                CommandClassic::Right => Self::Move(1),
-                CommandClassic::Input => Self::Buffer(BufferOptions::Input),
-                CommandClassic::Output => Self::Buffer(BufferOptions::Output),
+                CommandClassic::Increase(i) => Self::Add(1, 0, *i),
+                CommandClassic::Decrease(i) => Self::Add(-1, 0, *i),
+                CommandClassic::Left(i) => Self::Move(-1, *i),
+                CommandClassic::Right(i) => Self::Move(1, *i),
+                CommandClassic::Input(i) => Self::Buffer(BufferOptions::Input, 0, *i),
+                CommandClassic::Output(i) => Self::Buffer(BufferOptions::Output, 0, *i),
                 CommandClassic::OpenLoop(i) => Self::Loop(LoopOptions::PointerStart(None), *i),
                 CommandClassic::ClosedLoop(i) => Self::Loop(LoopOptions::PointerEnd(None), *i),
+                CommandClassic::Dump(i) => Self::Dump(*i),
             })
             .collect();
 
-        tokens = Self::add_move_reduce_tokens(&tokens.clone());
-        tokens = Self::loop_reduce_tokens(&tokens.clone());
+        let (tokens, stats) = optimize(tokens, config)?;
 
         // Return the generated tokens
-        Self::loop_conection(&tokens.clone())
+        Ok((Self::loop_conection(tokens)?, stats))
     }
 
-    fn add_move_reduce_tokens(commands: &Commands) -> Commands {
-        let mut tokens: Commands = Vec::with_capacity(commands.capacity());
-        let mut index = 0usize;
-
-        loop {
-            let token = commands.get(index);
-
-            match token {
-                Some(command) => match command {
-                    Self::Add(_) => {
-                        let (value, new_index) = Self::add_token(commands, index);
-                        if value != 0 {
-                            tokens.push(Self::Add(value));
-                        }
-                        index = new_index;
-                        continue;
+    // The commands between loops (a basic block) become adds and I/O at an offset from the pointer
+    // at the start of the block, followed by a single move
+    pub(super) fn add_move_reduce_tokens(commands: &[Self], config: &Config) -> Commands {
+        let mut tokens: Commands = Vec::with_capacity(commands.len());
+        let checked = config.overflow == OverflowPolicy::Error;
+        // value, offset, index_file of the adds not written yet
+        let mut adds: Vec<(i64, isize, usize)> = Vec::new();
+        let mut offset = 0isize;
+        let mut first_move = None;
+        // Lowest and highest offsets reached in the block, and the ones no command has checked yet
+        let mut reach = (0isize, 0isize);
+        let mut probes: [Option<(isize, usize)>; 2] = [None, None];
+        let (left_fails, right_fails) = match config.tape {
+            TapePolicy::Error => (true, true),
+            TapePolicy::GrowRight => (true, false),
+            TapePolicy::Wrap | TapePolicy::GrowBoth => (false, false),
+        };
+
+        for command in commands.iter().map(Some).chain([None]) {
+            if !matches!(command, Some(Self::Move(..))) {
+                Self::touch(&mut probes, offset);
+            }
+            match command {
+                Some(Self::Add(value, _, i)) => {
+                    // A checked add can fail, the pointer could have left the tape before
+                    if checked {
+                        Self::write_probes(&mut adds, &mut probes);
                     }
-                    Self::Move(_) => {
-                        let (value, new_index) = Self::move_token(commands, index);
-                        if value != 0 {
-                            tokens.push(Self::Move(value));
-                        }
-                        index = new_index;
-                        continue;
+                    Self::add_token(&mut adds, *value, offset, *i, config)
+                }
+                Some(Self::Move(pointer, i)) => {
+                    offset += *pointer;
+                    first_move.get_or_insert(*i);
+                    if offset < reach.0 {
+                        reach.0 = offset;
+                        probes[0] = left_fails.then_some((offset, *i));
                     }
-                    command => tokens.push(command.clone()),
-                },
-                None => break,
+                    if offset > reach.1 {
+                        reach.1 = offset;
+                        probes[1] = right_fails.then_some((offset, *i));
+                    }
+                }
+                // The adds are written first to keep the order of the I/O
+                Some(Self::Buffer(option, _, i)) => {
+                    Self::write_probes(&mut adds, &mut probes);
+                    Self::write_adds(&mut tokens, &mut adds);
+                    tokens.push(Self::Buffer(option.clone(), offset, *i));
+                }
+                // A loop or the end of the code ends the block
+                command => {
+                    Self::write_probes(&mut adds, &mut probes);
+                    Self::write_adds(&mut tokens, &mut adds);
+                    if let Some(i) = first_move.take().filter(|_| offset != 0) {
+                        tokens.push(Self::Move(offset, i));
+                    }
+                    offset = 0;
+                    reach = (0, 0);
+                    tokens.extend(command.cloned());
+                }
             }
-
-            index += 1;
         }
 
         tokens
     }
 
-    fn loop_reduce_tokens(commands: &Commands) -> Commands {
-        let mut tokens: Commands = Vec::with_capacity(commands.capacity());
+    // A command at `offset` checks that its cell is inside the tape
+    fn touch(probes: &mut [Option<(isize, usize)>; 2], offset: isize) {
+        for probe in probes.iter_mut() {
+            *probe = probe.filter(|(probe, _)| *probe != offset);
+        }
+    }
+
+    // The extremes reached without being checked become adds of 0, they fail when the cell is outside the tape
+    fn write_probes(adds: &mut Vec<(i64, isize, usize)>, probes: &mut [Option<(isize, usize)>; 2]) {
+        adds.extend(
+            probes
+                .iter_mut()
+                .filter_map(Option::take)
+                .map(|(offset, i)| (0, offset, i)),
+        );
+    }
+
+    pub(super) fn loop_reduce_tokens(commands: &[Self], config: &Config) -> Commands {
+        let mut tokens: Commands = Vec::with_capacity(commands.len());
         let mut index = 0usize;
 
         loop {
@@ -130,7 +259,7 @@ impl Command {
             match token {
                 Some(command) => match command {
                     Self::Loop(LoopOptions::PointerStart(_), i) => {
-                        let (value, new_index) = Self::loop_token(commands, index, *i);
+                        let (value, new_index) = Self::loop_token(commands, index, *i, config);
                         tokens.push(value);
                         index = new_index;
                         continue;
@@ -149,68 +278,284 @@ impl Command {
         tokens
     }
 
-    fn add_token(commands: &Commands, start: usize) -> (u8, usize) {
-        let mut counter = 0u8;
-        let mut end = start;
+    // Cells with a value known before running the program are set instead of incremented, `[-]+++` is a single set.
+    // A loop that can not end with the known value is an error before running the program.
+    pub(super) fn constant_fold_tokens(
+        commands: &[Self],
+        config: &Config,
+    ) -> Result<Commands, InterpreterError> {
+        let mut tokens: Commands = Vec::with_capacity(commands.len());
+        let checked = config.overflow == OverflowPolicy::Error;
+        let bounded = config.cell.bits().is_some();
+        let mut known = KnownCells::new(config);
+        // If every command before the current one always ends without errors, the position is known too
+        let mut reached = true;
+        let mut position = 0isize;
+
+        for command in commands {
+            let value = known.get(0);
+            match command {
+                Self::Add(increment, offset, i) => match known.added(*offset, *increment, config) {
+                    Some(value) => Self::set_token(&mut tokens, &known, value, *offset, *i, config),
+                    None => tokens.push(command.clone()),
+                },
+                Self::Loop(LoopOptions::Comment, i) => {
+                    if let Some(value) = value.filter(|value| reached && *value != 0) {
+                        return Err(InterpreterError::InfinityLoopFound(
+                            *i,
+                            config.cell.display(value),
+                            position,
+                        ));
+                    }
+                    tokens.push(command.clone());
+                }
+                Self::Loop(LoopOptions::AddToReset(step), i) => {
+                    // Checked bounded cells overflow instead
+                    let exact = !(checked && bounded);
+                    if let Some(value) = value.filter(|value| {
+                        reached && exact && !config.cell.reaches_zero(*value, *step)
+                    }) {
+                        return Err(InterpreterError::InfinityLoopFound(
+                            *i,
+                            config.cell.display(value),
+                            position,
+                        ));
+                    }
+                    // An odd step goes through every value of a bounded cell, decreasing by 1 stops at 0 without overflow
+                    let ends = value == Some(0)
+                        || (exact
+                            && value.is_some_and(|value| config.cell.reaches_zero(value, *step)))
+                        || (bounded && if checked { *step == -1 } else { step % 2 != 0 });
+                    if ends {
+                        Self::set_token(&mut tokens, &known, 0, 0, *i, config)
+                    } else {
+                        tokens.push(command.clone())
+                    }
+                }
+                command => tokens.push(command.clone()),
+            }
 
-        while let Some(Self::Add(value)) = commands.get(end) {
-            counter = counter.wrapping_add(*value);
-            end += 1;
+            // Every command writes a single token, it is the last one
+            if let Some(token) = tokens.last() {
+                reached &= known.always_ends(token, config);
+                if let Self::Move(pointer, _) = token {
+                    position = known.cell(position + *pointer);
+                }
+                known.apply(token, config);
+            }
         }
 
-        (counter, end)
+        Ok(tokens)
     }
 
-    fn move_token(commands: &Commands, start: usize) -> (u16, usize) {
-        let mut counter = 0u16;
-        let mut end = start;
+    // Loops entered when the current cell is known to be 0 never run, they are removed
+    pub(super) fn dead_loop_tokens(commands: &[Self], config: &Config) -> Commands {
+        let mut tokens: Commands = Vec::with_capacity(commands.len());
+        let mut known = KnownCells::new(config);
+        let mut index = 0usize;
 
-        while let Some(Self::Move(pointer)) = commands.get(end) {
-            counter = counter.wrapping_add(*pointer);
-            end += 1;
+        while let Some(command) = commands.get(index) {
+            let dead = known.get(0) == Some(0);
+            match command {
+                Self::Loop(LoopOptions::PointerEnd(_), _) => {}
+                // Without its end the loop is kept, the error is reported later
+                Self::Loop(LoopOptions::PointerStart(_), _) if dead => {
+                    if let Some(end) = Self::loop_end(commands, index) {
+                        index = end + 1;
+                        continue;
+                    }
+                }
+                Self::Loop(_, _) if dead => {
+                    index += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            known.apply(command, config);
+            tokens.push(command.clone());
+            index += 1;
         }
 
-        (counter, end)
+        tokens
     }
 
-    fn loop_token(commands: &Commands, start: usize, index_file: usize) -> (Self, usize) {
+    // Index of the end of the loop that starts at `start`
+    fn loop_end(commands: &[Self], start: usize) -> Option<usize> {
+        let mut depth = 0usize;
+        for (index, command) in commands.iter().enumerate().skip(start) {
+            match command {
+                Self::Loop(LoopOptions::PointerStart(_), _) => depth += 1,
+                Self::Loop(LoopOptions::PointerEnd(_), _) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(index);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    // The previous set of the same cell is replaced. Without overflow errors the adds and sets of other cells
+    // can be between them, with checked arithmetic only a consecutive set that does not overflow is replaced.
+    fn set_token(
+        tokens: &mut Commands,
+        known: &KnownCells,
+        value: i64,
+        offset: isize,
+        index_file: usize,
+        config: &Config,
+    ) {
+        let checked = config.overflow == OverflowPolicy::Error;
+        let mut index_file = index_file;
+        for index in (0..tokens.len()).rev() {
+            match &tokens[index] {
+                Self::Set(previous, o, i)
+                    if known.cell(*o) == known.cell(offset)
+                        && (!checked || config.cell.contains(*previous)) =>
+                {
+                    index_file = *i;
+                    tokens.remove(index);
+                    break;
+                }
+                Self::Add(_, o, _) | Self::Set(_, o, _)
+                    if known.cell(*o) != known.cell(offset) && !checked =>
+                {
+                    continue
+                }
+                _ => break,
+            }
+        }
+        tokens.push(Self::Set(value, offset, index_file));
+    }
+
+    fn add_token(
+        adds: &mut Vec<(i64, isize, usize)>,
+        value: i64,
+        offset: isize,
+        index_file: usize,
+        config: &Config,
+    ) {
+        match config.overflow {
+            // Wrapping adds can be reordered, they are merged with the first add to the same cell
+            OverflowPolicy::Wrap => match adds.iter_mut().find(|add| add.1 == offset) {
+                Some(add) => add.0 = config.cell.normalize(add.0 + value),
+                None => adds.push((value, offset, index_file)),
+            },
+            // Only consecutive adds in the same direction are merged, `-+` could underflow before the `+`
+            OverflowPolicy::Error => match adds.last_mut() {
+                Some(add) if add.1 == offset && add.0.signum() == value.signum() => add.0 += value,
+                _ => adds.push((value, offset, index_file)),
+            },
+        }
+    }
+
+    // Adds that cancel out are removed, except in other cells: the pointer has to be able to reach them
+    fn write_adds(tokens: &mut Commands, adds: &mut Vec<(i64, isize, usize)>) {
+        tokens.extend(
+            adds.drain(..)
+                .filter(|(value, offset, _)| *value != 0 || *offset != 0)
+                .map(|(value, offset, i)| Self::Add(value, offset, i)),
+        );
+    }
+
+    // Loop with only adds (its moves add up to 0) that decreases the current cell by 1 each iteration
+    fn multiply_add_token(
+        commands: &[Self],
+        start: usize,
+        config: &Config,
+    ) -> Option<(Vec<(isize, i64)>, usize)> {
+        let checked = config.overflow == OverflowPolicy::Error;
+        let mut end = None;
+        // offset, factor in the order of the body
+        let mut updates: Vec<(isize, i64)> = Vec::new();
+
+        for (index, command) in commands.iter().enumerate().skip(start + 1) {
+            match command {
+                Self::Add(value, offset, _) => {
+                    match updates.iter_mut().find(|update| update.0 == *offset) {
+                        // With checked arithmetic the fused adds of a cell are split only to keep the order of
+                        // the errors: a cell could overflow before or after the pointer leaves the tape
+                        Some(_) if checked => return None,
+                        Some(update) => update.1 = config.cell.normalize(update.1 + *value),
+                        None => updates.push((*offset, *value)),
+                    }
+                }
+                Self::Loop(LoopOptions::PointerEnd(_), _) => {
+                    end = Some(index + 1);
+                    break;
+                }
+                _ => return None,
+            }
+        }
+
+        let end = end?;
+        let control = updates.iter().position(|update| update.0 == 0)?;
+        if updates[control].1 != -1 {
+            return None;
+        }
+        updates.remove(control);
+        // `[-]` is a clear
+        if updates.is_empty() {
+            return None;
+        }
+
+        Some((updates, end))
+    }
+
+    fn loop_token(
+        commands: &[Self],
+        start: usize,
+        index_file: usize,
+        config: &Config,
+    ) -> (Self, usize) {
+        if let Some((updates, end)) = Self::multiply_add_token(commands, start, config) {
+            return (
+                Self::Loop(LoopOptions::MultiplyAdd(updates), index_file),
+                end,
+            );
+        }
+
         match (
             commands.get(start + 1),
             commands.get(start + 2),
             commands.get(start + 3),
-            commands.get(start + 4),
-            commands.get(start + 5),
         ) {
             (Some(Self::Loop(LoopOptions::PointerEnd(_), _)), ..) => {
                 (Self::Loop(LoopOptions::Comment, index_file), start + 2)
             }
-            (Some(Self::Add(value)), Some(Self::Loop(LoopOptions::PointerEnd(_), _)), ..) => (
-                Self::Loop(LoopOptions::AddToReset(*value), index_file), // value is even
+            (Some(Self::Add(value, 0, _)), Some(Self::Loop(LoopOptions::PointerEnd(_), _)), _) => (
+                Self::Loop(LoopOptions::AddToReset(*value), index_file), // a set when it always ends
                 start + 3,
             ),
-            (Some(Self::Move(pointer)), Some(Self::Loop(LoopOptions::PointerEnd(_), _)), ..) => (
+            (Some(Self::Move(pointer, _)), Some(Self::Loop(LoopOptions::PointerEnd(_), _)), _) => (
                 Self::Loop(LoopOptions::MoveToCell(*pointer), index_file),
                 start + 3,
             ),
             (
-                Some(Self::Add(value_1)),
-                Some(Self::Move(pointer_1)),
-                Some(Self::Add(value_2)),
-                Some(Self::Move(pointer_2)),
+                Some(Self::Add(value_1, 0, _)),
+                Some(Self::Add(value_2, pointer, _)),
                 Some(Self::Loop(LoopOptions::PointerEnd(_), _)),
-            )
-            | (
-                Some(Self::Move(pointer_1)),
-                Some(Self::Add(value_2)),
-                Some(Self::Move(pointer_2)),
-                Some(Self::Add(value_1)),
+            ) if *pointer != 0 => (
+                Self::Loop(
+                    LoopOptions::CutAdd(*pointer, *value_1, *value_2),
+                    index_file,
+                ),
+                start + 4,
+            ),
+            // With checked arithmetic the current cell has to be updated first, it could overflow
+            // before the pointer leaves the tape
+            (
+                Some(Self::Add(value_2, pointer, _)),
+                Some(Self::Add(value_1, 0, _)),
                 Some(Self::Loop(LoopOptions::PointerEnd(_), _)),
-            ) if (*pointer_1).wrapping_add(*pointer_2) == 0 => (
+            ) if *pointer != 0 && config.overflow == OverflowPolicy::Wrap => (
                 Self::Loop(
-                    LoopOptions::CutAdd(*pointer_1, *value_1, *value_2),
+                    LoopOptions::CutAdd(*pointer, *value_1, *value_2),
                     index_file,
                 ),
-                start + 6,
+                start + 4,
             ),
             _ => (
                 Self::Loop(LoopOptions::PointerStart(None), index_file),
@@ -219,9 +564,9 @@ impl Command {
         }
     }
 
-    fn loop_conection(commands: &Commands) -> Result<Commands, InterpreterError> {
+    fn loop_conection(mut commands: Commands) -> Result<Commands, InterpreterError> {
         let mut open_loop: Vec<(usize, usize)> = Vec::with_capacity(Self::token_counter(
-            commands,
+            &commands,
             Self::Loop(LoopOptions::PointerStart(None), 0),
         ));
         let mut loops: Vec<((usize, usize), (usize, usize))> =
@@ -242,7 +587,6 @@ impl Command {
             return Err(InterpreterError::MalformedOpenLoop(open_loop[0].1));
         }
 
-        let mut commands = commands.clone();
         while let Some((open_loop, closed_loop)) = loops.pop() {
             commands[open_loop.0] =
                 Self::Loop(LoopOptions::PointerStart(Some(closed_loop.0)), open_loop.1);
@@ -253,6 +597,18 @@ impl Command {
         Ok(commands)
     }
 
+    // Position in the source code of the first character of the command
+    pub fn index_file(&self) -> usize {
+        match self {
+            Self::Add(_, _, index_file)
+            | Self::Set(_, _, index_file)
+            | Self::Move(_, index_file)
+            | Self::Buffer(_, _, index_file)
+            | Self::Loop(_, index_file)
+            | Self::Dump(index_file) => *index_file,
+        }
+    }
+
     pub fn token_counter(commands: &[Self], token: Self) -> usize {
         let mut counter = 0usize;
 
@@ -266,6 +622,11 @@ impl Command {
                     Self::Loop(LoopOptions::PointerEnd(_), _),
                     Self::Loop(LoopOptions::PointerEnd(_), _),
                 ) => counter += 1,
+                (Self::Buffer(option, ..), Self::Buffer(token_option, ..)) => {
+                    if *option == token_option {
+                        counter += 1;
+                    }
+                }
                 _ => {
                     if *c == token {
                         counter += 1;
@@ -277,3 +638,135 @@ impl Command {
         counter
     }
 }
+
+// Values of the cells known before running the program, by offset from the pointer
+struct KnownCells {
+    cells: HashMap<isize, Option<i64>>, // `None` if the value is unknown
+    zero: bool,                         // if the other cells are 0
+    length: Option<isize>,              // length of a cyclic tape
+}
+
+impl KnownCells {
+    // Every cell starts with 0
+    fn new(config: &Config) -> Self {
+        Self {
+            cells: HashMap::new(),
+            zero: true,
+            // In a cyclic tape different offsets can be the same cell, it has at least one like `Tape::new`
+            length: (config.tape == TapePolicy::Wrap).then_some(config.tape_size.max(1) as isize),
+        }
+    }
+
+    // Cell of the offset, the same for every offset of the same cell
+    fn cell(&self, offset: isize) -> isize {
+        match self.length {
+            Some(length) => offset.rem_euclid(length),
+            None => offset,
+        }
+    }
+
+    fn get(&self, offset: isize) -> Option<i64> {
+        match self.cells.get(&self.cell(offset)) {
+            Some(value) => *value,
+            None => self.zero.then_some(0),
+        }
+    }
+
+    fn set(&mut self, offset: isize, value: Option<i64>) {
+        self.cells.insert(self.cell(offset), value);
+    }
+
+    // Value of the cell after adding `increment`, with checked arithmetic it can be out of the limits
+    fn added(&self, offset: isize, increment: i64, config: &Config) -> Option<i64> {
+        self.get(offset).map(|value| match config.overflow {
+            OverflowPolicy::Wrap => config.cell.normalize(value + increment),
+            OverflowPolicy::Error => value + increment,
+        })
+    }
+
+    // The pointer moves `offset` cells
+    fn shift(&mut self, offset: isize) {
+        let cells: Vec<(isize, Option<i64>)> = self.cells.drain().collect();
+        for (cell, value) in cells {
+            self.set(cell - offset, value);
+        }
+    }
+
+    fn forget(&mut self) {
+        self.cells.clear();
+        self.zero = false;
+    }
+
+    // If the command always ends without errors
+    fn always_ends(&self, command: &Command, config: &Config) -> bool {
+        let checked = config.overflow == OverflowPolicy::Error;
+        // Only a tape that does not grow or wrap can be left
+        let reachable = |offset: isize| {
+            offset == 0 || matches!(config.tape, TapePolicy::Wrap | TapePolicy::GrowBoth)
+        };
+        match command {
+            Command::Add(_, offset, _) => reachable(*offset) && !checked,
+            Command::Set(value, offset, _) => {
+                reachable(*offset) && (!checked || config.cell.contains(*value))
+            }
+            Command::Move(pointer, _) => reachable(*pointer),
+            Command::Buffer(BufferOptions::Input, ..) => false,
+            Command::Buffer(BufferOptions::Output, offset, _) => reachable(*offset),
+            // The body of a loop is only reached when it is entered
+            Command::Loop(LoopOptions::PointerStart(_), _) => false,
+            // A loop that is not entered
+            Command::Loop(_, _) => self.get(0) == Some(0),
+            Command::Dump(_) => true,
+        }
+    }
+
+    // Values after running the command
+    fn apply(&mut self, command: &Command, config: &Config) {
+        let checked = config.overflow == OverflowPolicy::Error;
+        match command {
+            // An overflow stops the program, the value after it is unknown
+            Command::Add(increment, offset, _) => {
+                let value = self.added(*offset, *increment, config);
+                self.set(
+                    *offset,
+                    value.filter(|value| !checked || config.cell.contains(*value)),
+                );
+            }
+            Command::Set(value, offset, _) => self.set(
+                *offset,
+                Some(*value).filter(|value| !checked || config.cell.contains(*value)),
+            ),
+            Command::Move(pointer, _) => self.shift(*pointer),
+            Command::Buffer(BufferOptions::Input, offset, _) => self.set(*offset, None),
+            Command::Buffer(BufferOptions::Output, ..) | Command::Dump(_) => {}
+            // A loop that is not entered changes nothing
+            Command::Loop(
+                LoopOptions::Comment
+                | LoopOptions::AddToReset(_)
+                | LoopOptions::MoveToCell(_)
+                | LoopOptions::CutAdd(..)
+                | LoopOptions::MultiplyAdd(_),
+                _,
+            ) if self.get(0) == Some(0) => {}
+            Command::Loop(LoopOptions::Comment | LoopOptions::AddToReset(_), _) => {
+                self.set(0, Some(0))
+            }
+            Command::Loop(LoopOptions::CutAdd(pointer, _, _), _) => {
+                self.set(*pointer, None);
+                self.set(0, Some(0));
+            }
+            Command::Loop(LoopOptions::MultiplyAdd(updates), _) => {
+                for (offset, _) in updates {
+                    self.set(*offset, None);
+                }
+                self.set(0, Some(0));
+            }
+            // The pointer ends in an unknown cell with 0
+            Command::Loop(LoopOptions::MoveToCell(_) | LoopOptions::PointerEnd(_), _) => {
+                self.forget();
+                self.set(0, Some(0));
+            }
+            Command::Loop(LoopOptions::PointerStart(_), _) => self.forget(),
+        }
+    }
+}
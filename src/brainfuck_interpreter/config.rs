@@ -0,0 +1,137 @@
+use std::{str::FromStr, time::Duration};
+
+use super::{
+    cell::{Cell, CellWidth},
+    error::InterpreterError,
+    optimizer::{OptLevel, Pass},
+    tape::TapePolicy,
+};
+
+// Value written by `,` when the input has ended
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum EofPolicy {
+    Unchanged, // the cell keeps its value
+    Zero,      // the cell is set to 0
+    MaxValue,  // the cell is set to -1 (255 in 8 bits)
+    Error,     // reading past the end of the input is an error
+    #[default]
+    ZeroThenError, // the first EOF sets the cell to 0, reading again is an error
+}
+
+impl FromStr for EofPolicy {
+    type Err = InterpreterError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "unchanged" => Ok(Self::Unchanged),
+            "zero" => Ok(Self::Zero),
+            "max" => Ok(Self::MaxValue),
+            "error" => Ok(Self::Error),
+            "zero-then-error" => Ok(Self::ZeroThenError),
+            _ => Err(InterpreterError::InvalidOption(
+                "--eof".to_string(),
+                name.to_string(),
+            )),
+        }
+    }
+}
+
+// What happens when a cell goes past its minimum or maximum value
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OverflowPolicy {
+    #[default]
+    Wrap, // the value is traversed cyclically
+    Error, // overflow and underflow are errors
+}
+
+impl OverflowPolicy {
+    // Increment the value of a cell following the policy
+    pub fn add<C: Cell>(
+        &self,
+        value: &C,
+        increment: i64,
+        index_file: usize,
+        position: isize,
+    ) -> Result<C, InterpreterError> {
+        match self {
+            Self::Wrap => Ok(value.add(increment)),
+            Self::Error => value
+                .checked_add(increment)
+                .ok_or(InterpreterError::CellOverflow(index_file, position)),
+        }
+    }
+
+    // Increment the value of a cell by `factor * multiplier` following the policy
+    pub fn multiply_add<C: Cell>(
+        &self,
+        value: &C,
+        factor: i64,
+        multiplier: &C,
+        index_file: usize,
+        position: isize,
+    ) -> Result<C, InterpreterError> {
+        match self {
+            Self::Wrap => Ok(value.multiply_add(factor, multiplier)),
+            Self::Error => value
+                .checked_multiply_add(factor, multiplier)
+                .ok_or(InterpreterError::CellOverflow(index_file, position)),
+        }
+    }
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = InterpreterError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "wrap" => Ok(Self::Wrap),
+            "error" => Ok(Self::Error),
+            _ => Err(InterpreterError::InvalidOption(
+                "--overflow".to_string(),
+                name.to_string(),
+            )),
+        }
+    }
+}
+
+// Execution settings of the interpreter
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Config {
+    pub eof: EofPolicy,
+    pub tape_size: usize,
+    pub tape: TapePolicy,
+    pub cell: CellWidth,
+    pub overflow: OverflowPolicy,
+    pub max_steps: Option<u64>, // instructions executed, an optimized loop is a single instruction
+    pub timeout: Option<Duration>,
+    pub passes: Vec<Pass>, // optimization passes enabled, they run in the order of `Pass::ALL`
+    pub jit: bool, // compile to machine code when the `jit` feature and the platform support it
+    pub debug_dump: bool, // `#` is an instruction that prints the cells around the pointer to stderr
+}
+
+impl Config {
+    pub fn set_pass(&mut self, pass: Pass, enabled: bool) {
+        self.passes.retain(|p| *p != pass);
+        if enabled {
+            self.passes.push(pass);
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            eof: EofPolicy::default(),
+            tape_size: u16::MAX as usize + 1,
+            tape: TapePolicy::default(),
+            cell: CellWidth::default(),
+            overflow: OverflowPolicy::default(),
+            max_steps: None,
+            timeout: None,
+            passes: OptLevel::default().passes(),
+            jit: true,
+            debug_dump: false,
+        }
+    }
+}
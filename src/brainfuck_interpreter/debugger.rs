@@ -0,0 +1,133 @@
+use super::{error::InterpreterError, interpreter::Interpreter};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{Read, Write},
+};
+
+// Why the debugger returned control
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stop {
+    Stepped,                           // the requested steps have been executed
+    Breakpoint(usize),                 // the next command starts at this source position
+    Watchpoint(isize, String, String), // cell, previous value and new value
+    Halted,                            // the program has finished
+}
+
+// Step-by-step execution of a loaded program, one command of `run_code` at a time
+pub struct Debugger {
+    interpreter: Interpreter,
+    breakpoints: BTreeSet<usize>,         // source positions
+    watchpoints: BTreeMap<isize, String>, // cell and last value seen
+}
+
+impl Debugger {
+    // `interpreter` must have a program loaded, it is resumed from where it is
+    pub fn new(interpreter: Interpreter) -> Self {
+        Self {
+            interpreter,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+        }
+    }
+
+    pub fn interpreter(&self) -> &Interpreter {
+        &self.interpreter
+    }
+
+    // Stop before the command that starts at `position` of the source code, the optimized commands start
+    // at fewer positions than the code has instructions
+    pub fn add_breakpoint(&mut self, position: usize) -> Result<(), InterpreterError> {
+        let tokens = self.interpreter.tokens().unwrap_or_default();
+        if !tokens.iter().any(|token| token.index_file() == position) {
+            return Err(InterpreterError::NoInstructionAt(position));
+        }
+        self.breakpoints.insert(position);
+        Ok(())
+    }
+
+    pub fn remove_breakpoint(&mut self, position: usize) -> bool {
+        self.breakpoints.remove(&position)
+    }
+
+    pub fn breakpoints(&self) -> Vec<usize> {
+        self.breakpoints.iter().copied().collect()
+    }
+
+    // Stop after a command changes the value of `cell`
+    pub fn add_watchpoint(&mut self, cell: isize) {
+        self.watchpoints.insert(cell, self.value(cell));
+    }
+
+    pub fn remove_watchpoint(&mut self, cell: isize) -> bool {
+        self.watchpoints.remove(&cell).is_some()
+    }
+
+    pub fn watchpoints(&self) -> Vec<isize> {
+        self.watchpoints.keys().copied().collect()
+    }
+
+    // A cell outside the tape is 0 until the tape grows to reach it
+    fn value(&self, cell: isize) -> String {
+        self.interpreter
+            .cell(cell)
+            .unwrap_or_else(|| "0".to_string())
+    }
+
+    // Run at most `steps` commands, stopping earlier at a breakpoint or a watchpoint
+    pub fn step(
+        &mut self,
+        steps: u64,
+        input: Option<&mut dyn Read>,
+        output: &mut dyn Write,
+    ) -> Result<Stop, InterpreterError> {
+        self.run(Some(steps), input, output)
+    }
+
+    // Run until a breakpoint, a watchpoint or the end of the program
+    pub fn resume(
+        &mut self,
+        input: Option<&mut dyn Read>,
+        output: &mut dyn Write,
+    ) -> Result<Stop, InterpreterError> {
+        self.run(None, input, output)
+    }
+
+    // The command at the current breakpoint is always executed, so resuming leaves it
+    fn run(
+        &mut self,
+        steps: Option<u64>,
+        mut input: Option<&mut dyn Read>,
+        output: &mut dyn Write,
+    ) -> Result<Stop, InterpreterError> {
+        let mut executed = 0;
+        while steps.is_none_or(|steps| executed < steps) {
+            if self.interpreter.is_halted() {
+                return Ok(Stop::Halted);
+            }
+            self.interpreter
+                .run_steps(1, input.as_mut().map(|input| &mut **input as _), output)?;
+            executed += 1;
+
+            // The last command can change a watched cell too
+            let changed = self
+                .watchpoints
+                .iter()
+                .map(|(cell, last)| (*cell, last, self.value(*cell)))
+                .find(|(_, last, value)| *last != value)
+                .map(|(cell, _, value)| (cell, value));
+            if let Some((cell, value)) = changed {
+                let previous = self.watchpoints.insert(cell, value.clone());
+                return Ok(Stop::Watchpoint(cell, previous.unwrap_or_default(), value));
+            }
+            if self.interpreter.is_halted() {
+                return Ok(Stop::Halted);
+            }
+            if let Some(command) = self.interpreter.current_command() {
+                if self.breakpoints.contains(&command.index_file()) {
+                    return Ok(Stop::Breakpoint(command.index_file()));
+                }
+            }
+        }
+        Ok(Stop::Stepped)
+    }
+}
@@ -2,35 +2,129 @@ use thiserror::Error;
 
 // Define custom error types using the `thiserror` crate
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum InterpreterError {
     #[error("Usage: {0}")]
     SintaxisError(String),
-    #[error("Unknown character instruction `{0}` at code position `{1}`")]
+    #[error("{}", self.message(None))]
     InstruccionUnknown(char, usize),
+    #[error("Invalid value `{1}` for option `{0}`")]
+    InvalidOption(String, String),
     #[error("Cannot parse argument `{0}`: {1}")]
     ParseError(String, #[source] std::num::ParseIntError),
-    #[error("Closed loop does not match an open loop at index: `{0}`")]
+    #[error("Cannot read source `{0}`: {1}")]
+    ReadError(String, #[source] std::io::Error),
+    #[error("Cannot write trace `{0}`: {1}")]
+    TraceWriteError(String, #[source] std::io::Error),
+    #[error("{}", self.message(None))]
     MalformedClosedLoop(usize),
-    #[error("Open loop does not match a closed loop at index: `{0}`")]
+    #[error("{}", self.message(None))]
     MalformedOpenLoop(usize),
-    #[error("An infinite loop has been found, at code position `{0}`, with a current value `{1}` in memory cell `{2}`")]
-    InfinityLoopFound(usize, u8, usize),
-    #[error("An infinite movement loop has been found, at code position `{0}`, memory does not contain value `0`")]
+    #[error("{}", self.message(None))]
+    InfinityLoopFound(usize, String, isize),
+    #[error("{}", self.message(None))]
     InfinityLoopMemoryFull(usize),
-    #[error("An infinite movement loop has been found, at code position `{0}`, the pointer never stops moving, memory cell movement is `{1}`")]
-    InfinityLoopMovement(usize, u16),
+    #[error("{}", self.message(None))]
+    InfinityLoopMovement(usize, isize),
+    #[error("{}", self.message(None))]
+    PointerOutOfBounds(usize, isize),
+    #[error("{}", self.message(None))]
+    CellOverflow(usize, isize),
+    #[error("{}", self.message(None))]
+    StepLimitReached(u64, usize),
+    #[error("{}", self.message(None))]
+    TimeoutReached(u64, usize),
     #[error("Missing arguments")]
     MissingArgs,
     #[error("Execution Error: Tokens not loaded")]
     TokensUnknown,
-    #[error("Execution Error: Tokens cannot be overwritten")]
-    TokensOverwritten,
-    #[error("Execution Error: Args cannot be overwritten")]
-    ArgsOverwritten,
     #[error("Execution Error: No code was executed")]
     OutputUnknown,
+    #[error("Execution Error: Input/output failure: {0}")]
+    IoError(#[source] std::io::Error),
     #[error("Execution Error: Unconnected loops")]
     UnconnectedLoops,
-    #[error("Unexpected Error: Modifying output value")]
-    OutputOverwritten,
+    #[error("{}", self.message(None))]
+    NoInstructionAt(usize),
+    #[error("Invalid trace file: {0}")]
+    InvalidTrace(String),
+    #[error("{}\n{2}", .0.message(Some(.1)))]
+    InSource(Box<InterpreterError>, String, String), // error, its `name:line:column` and the snippet of the source code, see `SourceMap::locate`
+}
+
+impl InterpreterError {
+    // Position in the source code where the error happened
+    pub fn index_file(&self) -> Option<usize> {
+        match self {
+            Self::InstruccionUnknown(_, index_file)
+            | Self::MalformedClosedLoop(index_file)
+            | Self::MalformedOpenLoop(index_file)
+            | Self::InfinityLoopFound(index_file, _, _)
+            | Self::InfinityLoopMemoryFull(index_file)
+            | Self::InfinityLoopMovement(index_file, _)
+            | Self::PointerOutOfBounds(index_file, _)
+            | Self::CellOverflow(index_file, _)
+            | Self::StepLimitReached(_, index_file)
+            | Self::TimeoutReached(_, index_file)
+            | Self::NoInstructionAt(index_file) => Some(*index_file),
+            Self::InSource(error, ..) => error.index_file(),
+            _ => None,
+        }
+    }
+
+    // Message of the error, with `location` instead of its code position when the source code is known
+    pub fn message(&self, location: Option<&str>) -> String {
+        let at = |label: &str, index_file: &usize| match location {
+            Some(location) => format!("`{location}`"),
+            None => format!("{label} `{index_file}`"),
+        };
+        match self {
+            Self::InstruccionUnknown(character, index_file) => format!(
+                "Unknown character instruction `{character}` at {}",
+                at("code position", index_file)
+            ),
+            Self::MalformedClosedLoop(index_file) => format!(
+                "Closed loop does not match an open loop at {}",
+                at("index:", index_file)
+            ),
+            Self::MalformedOpenLoop(index_file) => format!(
+                "Open loop does not match a closed loop at {}",
+                at("index:", index_file)
+            ),
+            Self::InfinityLoopFound(index_file, value, cell) => format!(
+                "An infinite loop has been found, at {}, with a current value `{value}` in memory cell `{cell}`",
+                at("code position", index_file)
+            ),
+            Self::InfinityLoopMemoryFull(index_file) => format!(
+                "An infinite movement loop has been found, at {}, memory does not contain value `0`",
+                at("code position", index_file)
+            ),
+            Self::InfinityLoopMovement(index_file, movement) => format!(
+                "An infinite movement loop has been found, at {}, the pointer never stops moving, memory cell movement is `{movement}`",
+                at("code position", index_file)
+            ),
+            Self::PointerOutOfBounds(index_file, cell) => format!(
+                "The pointer has left the tape at {}, from memory cell `{cell}`",
+                at("code position", index_file)
+            ),
+            Self::CellOverflow(index_file, cell) => format!(
+                "The value of memory cell `{cell}` has overflowed at {}",
+                at("code position", index_file)
+            ),
+            Self::StepLimitReached(steps, index_file) => format!(
+                "Execution stopped after `{steps}` steps at {}: step limit reached",
+                at("code position", index_file)
+            ),
+            Self::TimeoutReached(steps, index_file) => format!(
+                "Execution stopped after `{steps}` steps at {}: time limit reached",
+                at("code position", index_file)
+            ),
+            Self::NoInstructionAt(index_file) => format!(
+                "No instruction starts at {}, see `dump-ir` or debug with `-O0`",
+                at("code position", index_file)
+            ),
+            Self::InSource(error, ..) => error.message(location),
+            error => error.to_string(),
+        }
+    }
 }
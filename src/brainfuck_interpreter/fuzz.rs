@@ -0,0 +1,184 @@
+use super::{
+    builder::InterpreterBuilder,
+    cell::CellWidth,
+    command::ParseMode,
+    config::{Config, EofPolicy, OverflowPolicy},
+    interpreter::Data,
+    optimizer::Pass,
+    oracle::{differential_test, Verdict},
+    tape::TapePolicy,
+};
+
+// Loops that the optimizer rewrites, they are mixed with random code
+const IDIOMS: [&str; 21] = [
+    "[-]",
+    "[+]",
+    "[--]",
+    "[---]",
+    "[]",
+    "[>]",
+    "[<]",
+    "[>>]",
+    "[<<<]",
+    "[->+<]",
+    "[-<+>]",
+    "[>+<-]",
+    "[<->-]",
+    "[->>+<<]",
+    "[->+>+<<]",
+    "[->++>+++<<]",
+    "[-->+<]",
+    "[>-<---]",
+    "[+>+<]",
+    "[->-<]",
+    "[->-->-<<]",
+];
+
+const MAX_DEPTH: u64 = 3;
+const MAX_STEPS: u64 = 100_000;
+
+// Pseudo-random generator (xorshift64*), the same seed always generates the same programs
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, limit: u64) -> u64 {
+        self.next() % limit
+    }
+}
+
+// Program that produced a different result in the optimized interpreter
+#[derive(Debug)]
+pub struct FuzzFailure {
+    pub code: String,
+    pub args: Option<Data>,
+    pub config: Config,
+    pub description: String,
+}
+
+#[derive(Debug, Default)]
+pub struct FuzzReport {
+    pub programs: u64,
+    pub inconclusive: u64,
+    pub failures: Vec<FuzzFailure>,
+}
+
+// Diff-test `iterations` random programs, inputs and configurations against the reference interpreter
+pub fn fuzz(seed: u64, iterations: u64) -> FuzzReport {
+    // xorshift never leaves the state 0
+    let mut random = Random(seed.max(1));
+    let mut report = FuzzReport::default();
+
+    for _ in 0..iterations {
+        let code = program(&mut random, 0);
+        let args = match random.below(4) {
+            0 => None,
+            _ => Some(
+                (0..random.below(5))
+                    .map(|_| random.below(256) as u8)
+                    .collect(),
+            ),
+        };
+        let config = configuration(&mut random);
+
+        report.programs += 1;
+        match differential_test(&code, ParseMode::Strict, args.as_deref(), &config) {
+            Verdict::Match => {}
+            Verdict::Inconclusive => report.inconclusive += 1,
+            Verdict::Mismatch(description) => report.failures.push(FuzzFailure {
+                code,
+                args,
+                config,
+                description,
+            }),
+        }
+    }
+
+    report
+}
+
+fn program(random: &mut Random, depth: u64) -> String {
+    let mut code = String::new();
+    for _ in 0..random.below(8) + 1 {
+        match random.below(10) {
+            0..=5 => code.push(['+', '-', '<', '>', '.', ','][random.below(6) as usize]),
+            6 | 7 => code.push_str(IDIOMS[random.below(IDIOMS.len() as u64) as usize]),
+            _ if depth < MAX_DEPTH => {
+                code.push('[');
+                code.push_str(&program(random, depth + 1));
+                code.push(']');
+            }
+            _ => code.push('+'),
+        }
+    }
+
+    code
+}
+
+fn configuration(random: &mut Random) -> Config {
+    let builder = InterpreterBuilder::new()
+        .eof(
+            [
+                EofPolicy::Unchanged,
+                EofPolicy::Zero,
+                EofPolicy::MaxValue,
+                EofPolicy::Error,
+                EofPolicy::ZeroThenError,
+            ][random.below(5) as usize],
+        )
+        .tape_size(random.below(16) as usize + 1)
+        .tape(
+            [
+                TapePolicy::Wrap,
+                TapePolicy::Error,
+                TapePolicy::GrowRight,
+                TapePolicy::GrowBoth,
+            ][random.below(4) as usize],
+        )
+        .cell(
+            [
+                CellWidth::Bits8,
+                CellWidth::Bits16,
+                CellWidth::Bits32,
+                CellWidth::Unbounded,
+            ][random.below(4) as usize],
+        )
+        .overflow(match random.below(3) {
+            0 => OverflowPolicy::Error,
+            _ => OverflowPolicy::Wrap,
+        })
+        .max_steps(MAX_STEPS);
+    // Each pass is also checked without the others
+    Pass::ALL
+        .into_iter()
+        .fold(builder, |builder, pass| {
+            builder.pass(pass, random.below(4) != 0)
+        })
+        .config()
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fuzz;
+
+    #[test]
+    fn fixed_seeds_match_the_reference() {
+        // 23 and 46 find a multiply loop whose second target overflows first
+        for seed in [1, 2, 3, 23, 42, 46] {
+            let report = fuzz(seed, 500);
+            assert!(
+                report.failures.is_empty(),
+                "seed {}: {:?}",
+                seed,
+                report.failures
+            );
+        }
+    }
+}
@@ -1,199 +1,774 @@
+#[cfg(all(feature = "jit", target_os = "linux", target_arch = "x86_64"))]
+use super::jit;
 use super::{
+    builder::InterpreterBuilder,
+    cell::{Cell, CellWidth},
     command::{BufferOptions, Command, Commands, LoopOptions},
+    config::{Config, EofPolicy, OverflowPolicy},
     error::InterpreterError,
+    profiler::{Profile, Profiler},
+    source::{Position, SourceMap},
+    tape::{ScanFailure, Tape},
+    trace::Tracer,
+};
+use num_bigint::BigInt;
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    time::Instant,
 };
-use std::cell::OnceCell;
 
 pub type Data = Vec<u8>;
 
-// Struct to represent the Brainfuck interpreter
+const DEADLINE_CHECK_STEPS: u64 = 1024;
+// Cells at each side of the pointer printed by `#`
+pub(super) const DUMP_RADIUS: usize = 8;
+
+// Memory of the interpreter with the cell width of the configuration
+enum Memory {
+    Bits8(Tape<u8>),
+    Bits16(Tape<u16>),
+    Bits32(Tape<u32>),
+    Unbounded(Tape<BigInt>),
+}
+
+impl Memory {
+    fn new(config: &Config) -> Self {
+        match config.cell {
+            CellWidth::Bits8 => Self::Bits8(Tape::new(config.tape_size, config.tape)),
+            CellWidth::Bits16 => Self::Bits16(Tape::new(config.tape_size, config.tape)),
+            CellWidth::Bits32 => Self::Bits32(Tape::new(config.tape_size, config.tape)),
+            CellWidth::Unbounded => Self::Unbounded(Tape::new(config.tape_size, config.tape)),
+        }
+    }
+}
+
+// Registers of the virtual machine, saved between runs to resume the execution
 #[derive(Default)]
+pub(super) struct Registers {
+    pub(super) token_index: usize, // program counter
+    pub(super) steps: u64,
+    pub(super) input_ended: bool, // the end of the input has already been read
+}
+
+// State of the program when a run returns
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Halted, // the program has finished
+    Paused, // the step budget of the run has been used, it can be resumed
+}
+
+// Struct to represent the Brainfuck interpreter
 pub struct Interpreter {
-    args: OnceCell<Data>,
-    output: OnceCell<Data>,
-    tokens: OnceCell<Commands>,
+    config: Config,
+    tokens: Option<Commands>,
+    memory: Memory,
+    registers: Registers,
+    output: Option<Data>,
+    hooks: Hooks,
+    source: Option<SourceMap>, // source code of the loaded program, its errors are located in it
+}
+
+// Observers of every command executed, the JIT is not used while one is set
+#[derive(Default)]
+struct Hooks {
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    dump: Option<Box<dyn Write>>, // `None` prints the `#` dumps to stderr
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new(Config::default())
+    }
 }
 
 impl Interpreter {
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder::new()
+    }
+
     // Constructor to create a new Brainfuck interpreter instance
-    pub fn new() -> Self {
-        Self::default()
+    pub fn new(config: Config) -> Self {
+        Self {
+            memory: Memory::new(&config),
+            config,
+            tokens: None,
+            registers: Registers::default(),
+            output: None,
+            hooks: Hooks::default(),
+            source: None,
+        }
     }
 
-    fn set_args(&mut self, args: Data) -> Result<(), InterpreterError> {
-        self.args
-            .set(args)
-            .map_err(|_| InterpreterError::ArgsOverwritten)?;
+    // The errors and the `#` dumps of the run show their line and column in `source`, `None` keeps the index
+    pub fn set_source(&mut self, source: Option<SourceMap>) {
+        self.source = source;
+    }
 
-        Ok(())
+    // Line and column of the next command in the source code
+    pub fn position(&self) -> Option<Position> {
+        let source = self.source.as_ref()?;
+        Some(source.position(self.current_command()?.index_file()))
+    }
+
+    // Record every command executed from now on, `None` stops recording
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.hooks.tracer = tracer;
+    }
+
+    // Count the executions and time of every command from now on, `None` stops counting
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.hooks.profiler = profiler;
+    }
+
+    // Write the dumps of `#` to `writer` from now on, `None` prints them to stderr
+    pub fn set_dump_output(&mut self, writer: Option<Box<dyn Write>>) {
+        self.hooks.dump = writer;
+    }
+
+    // Results of the profiler for the loaded program, `source` is its source code
+    pub fn profile(&self, source: &SourceMap) -> Option<Profile> {
+        let profiler = self.hooks.profiler.as_ref()?;
+        let tokens = self.tokens.as_ref()?;
+        Some(profiler.report(tokens, source))
+    }
+
+    // Load a program, the state of the previous one is discarded
+    pub fn load(&mut self, tokens: Commands) {
+        self.tokens = Some(tokens);
+        self.reset();
     }
 
-    fn set_tokens(&self, tokens: Commands) -> Result<(), InterpreterError> {
+    // Clear the memory, the registers and the output to run the loaded program from the start
+    pub fn reset(&mut self) {
+        self.memory = Memory::new(&self.config);
+        self.registers = Registers::default();
+        self.output = None;
+    }
+
+    pub fn is_halted(&self) -> bool {
         self.tokens
-            .set(tokens)
-            .map_err(|_| InterpreterError::TokensOverwritten)?;
+            .as_ref()
+            .is_some_and(|tokens| self.registers.token_index >= tokens.len())
+    }
 
-        Ok(())
+    pub fn steps(&self) -> u64 {
+        self.registers.steps
     }
 
-    // Execute the Brainfuck code
-    fn run_code(&mut self) -> Result<(), InterpreterError> {
-        if self.output.get().is_some() {
-            return Ok(());
+    // Index of the next command to execute
+    pub fn token_index(&self) -> usize {
+        self.registers.token_index
+    }
+
+    // Cell number of the pointer
+    pub fn pointer(&self) -> isize {
+        match &self.memory {
+            Memory::Bits8(tape) => tape.position(),
+            Memory::Bits16(tape) => tape.position(),
+            Memory::Bits32(tape) => tape.position(),
+            Memory::Unbounded(tape) => tape.position(),
         }
+    }
 
-        match self.tokens.get() {
-            Some(tokens) => {
-                let mut memory = [0u8; u16::MAX as usize + 1];
-                let mut memory_pointer = 0usize;
-                let mut output: Data =
-                    // It is an initial value; the true one is unknown because it could be within a loop, hence it could be greater (if it repeats any loop) or smaller (if it didn't enter any loop).
-                    Vec::with_capacity(Command::token_counter(tokens, Command::Buffer(BufferOptions::Output)));
-                let mut token_index = 0usize;
-                let mut args: Option<Data> = self.args.get().cloned();
+    // Cell number and value of every cell different from 0
+    pub fn non_zero_cells(&self) -> Vec<(isize, String)> {
+        match &self.memory {
+            Memory::Bits8(tape) => tape.non_zero_cells(),
+            Memory::Bits16(tape) => tape.non_zero_cells(),
+            Memory::Bits32(tape) => tape.non_zero_cells(),
+            Memory::Unbounded(tape) => tape.non_zero_cells(),
+        }
+    }
 
-                while let Some(token) = tokens.get(token_index) {
-                    // Match each command and perform the corresponding operation
-                    match token {
-                        Command::Add(increment) => {
-                            memory[memory_pointer] = memory[memory_pointer].wrapping_add(*increment)
-                        }
-                        Command::Move(pointer) => {
-                            memory_pointer = (memory_pointer as u16).wrapping_add(*pointer) as usize
-                        }
-                        Command::Buffer(BufferOptions::Input) => match args.as_mut() {
-                            Some(bf_args) => match bf_args.pop() {
-                                Some(value) => memory[memory_pointer] = value,
-                                None => {
-                                    memory[memory_pointer] = 0;
-                                    args = None;
-                                } // EOF
-                            },
-                            None => return Err(InterpreterError::MissingArgs),
-                        },
-                        Command::Buffer(BufferOptions::Output) => {
-                            output.push(memory[memory_pointer])
-                        }
-                        Command::Loop(LoopOptions::PointerStart(None), _)
-                        | Command::Loop(LoopOptions::PointerEnd(None), _) => {
-                            return Err(InterpreterError::UnconnectedLoops)
-                        }
-                        Command::Loop(LoopOptions::Comment, index_file) => {
-                            if memory[memory_pointer] != 0 {
-                                return Err(InterpreterError::InfinityLoopFound(
-                                    *index_file,
-                                    memory[memory_pointer],
-                                    memory_pointer,
-                                ));
-                            }
-                        }
-                        Command::Loop(LoopOptions::AddToReset(value), index_file) => {
-                            let memory_value_start = memory[memory_pointer];
-                            loop {
-                                if memory[memory_pointer] == 0 {
-                                    break;
-                                }
-                                memory[memory_pointer] =
-                                    memory[memory_pointer].wrapping_add(*value);
-                                if memory[memory_pointer] == memory_value_start {
-                                    return Err(InterpreterError::InfinityLoopFound(
-                                        *index_file,
-                                        memory[memory_pointer],
-                                        memory_pointer,
-                                    ));
-                                }
-                            }
-                        }
-                        Command::Loop(LoopOptions::MoveToCell(pointer), index_file) => {
-                            let is_not_empty = memory.iter().all(|&m| m != 0);
-                            if is_not_empty {
-                                return Err(InterpreterError::InfinityLoopMemoryFull(*index_file));
-                            }
-                            let memory_pointer_start = memory_pointer;
-                            loop {
-                                if memory[memory_pointer] == 0 {
-                                    break;
-                                }
-                                memory_pointer =
-                                    (memory_pointer as u16).wrapping_add(*pointer) as usize;
-                                if memory_pointer == memory_pointer_start {
-                                    return Err(InterpreterError::InfinityLoopMovement(
-                                        *index_file,
-                                        *pointer,
-                                    ));
-                                }
-                            }
-                        }
-                        Command::Loop(
-                            LoopOptions::CutAdd(pointer, value_1, value_2),
-                            index_file,
-                        ) => {
-                            let memory_value_start = memory[memory_pointer];
-                            let pointer_momevent =
-                                (memory_pointer as u16).wrapping_add(*pointer) as usize;
-                            loop {
-                                if memory[memory_pointer] == 0 {
-                                    break;
-                                }
-                                memory[memory_pointer] =
-                                    memory[memory_pointer].wrapping_add(*value_1);
-                                memory[pointer_momevent] =
-                                    memory[pointer_momevent].wrapping_add(*value_2);
-                                if memory[memory_pointer] == memory_value_start {
-                                    return Err(InterpreterError::InfinityLoopFound(
-                                        *index_file,
-                                        memory[memory_pointer],
-                                        memory_pointer,
-                                    ));
-                                }
-                            }
-                        }
-                        Command::Loop(LoopOptions::PointerStart(Some(pointer)), _) => {
-                            if memory[memory_pointer] == 0 {
-                                token_index = *pointer;
-                            }
-                        }
-                        Command::Loop(LoopOptions::PointerEnd(Some(pointer)), _) => {
-                            if memory[memory_pointer] != 0 {
-                                token_index = *pointer;
-                            }
+    // Next command to execute, `None` when the program has halted
+    // Commands of the loaded program
+    pub fn tokens(&self) -> Option<&[Command]> {
+        self.tokens.as_deref()
+    }
+
+    pub fn current_command(&self) -> Option<&Command> {
+        self.tokens.as_ref()?.get(self.registers.token_index)
+    }
+
+    // Value of the cell at `position`, `None` if it is outside the tape
+    pub fn cell(&self, position: isize) -> Option<String> {
+        match &self.memory {
+            Memory::Bits8(tape) => tape.cell(position).map(u8::to_string),
+            Memory::Bits16(tape) => tape.cell(position).map(u16::to_string),
+            Memory::Bits32(tape) => tape.cell(position).map(u32::to_string),
+            Memory::Unbounded(tape) => tape.cell(position).map(BigInt::to_string),
+        }
+    }
+
+    // Position and value of the cells around the pointer
+    pub fn tape_window(&self, radius: usize) -> Vec<(isize, String)> {
+        match &self.memory {
+            Memory::Bits8(tape) => tape.window(radius),
+            Memory::Bits16(tape) => tape.window(radius),
+            Memory::Bits32(tape) => tape.window(radius),
+            Memory::Unbounded(tape) => tape.window(radius),
+        }
+    }
+
+    // Read a single byte, `None` at the end of the input
+    pub(super) fn read_byte(input: &mut dyn Read) -> Result<Option<u8>, InterpreterError> {
+        let mut buffer = [0u8; 1];
+        loop {
+            match input.read(&mut buffer) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(buffer[0])),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(InterpreterError::IoError(e)),
+            }
+        }
+    }
+
+    // Run the loaded program until it halts or `budget` steps are executed, `input` is `None` when there are no arguments to read
+    fn run_code(
+        &mut self,
+        input: Option<&mut dyn Read>,
+        output: &mut dyn Write,
+        budget: Option<u64>,
+    ) -> Result<Status, InterpreterError> {
+        let tokens = self
+            .tokens
+            .as_ref()
+            .ok_or(InterpreterError::TokensUnknown)?;
+        let (config, registers) = (&self.config, &mut self.registers);
+        let (hooks, source) = (&mut self.hooks, self.source.as_ref());
+        let result = match &mut self.memory {
+            Memory::Bits8(tape) => Self::run_tape(
+                config, tokens, tape, registers, input, output, budget, hooks, source,
+            ),
+            Memory::Bits16(tape) => Self::run_tape(
+                config, tokens, tape, registers, input, output, budget, hooks, source,
+            ),
+            Memory::Bits32(tape) => Self::run_tape(
+                config, tokens, tape, registers, input, output, budget, hooks, source,
+            ),
+            Memory::Unbounded(tape) => Self::run_tape(
+                config, tokens, tape, registers, input, output, budget, hooks, source,
+            ),
+        };
+        // The steps before an error are kept in the trace too
+        if let Some(tracer) = &mut self.hooks.tracer {
+            tracer.flush()?;
+        }
+        if let Some(dump) = &mut self.hooks.dump {
+            dump.flush().map_err(InterpreterError::IoError)?;
+        }
+        result.map_err(|error| match &self.source {
+            Some(source) => source.locate(error),
+            None => error,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn run_tape<C: Cell>(
+        config: &Config,
+        tokens: &[Command],
+        tape: &mut Tape<C>,
+        registers: &mut Registers,
+        mut input: Option<&mut dyn Read>,
+        output: &mut dyn Write,
+        budget: Option<u64>,
+        hooks: &mut Hooks,
+        source: Option<&SourceMap>,
+    ) -> Result<Status, InterpreterError> {
+        // A program is compiled when it runs from the start until the end without hooks
+        #[cfg(all(feature = "jit", target_os = "linux", target_arch = "x86_64"))]
+        if budget.is_none()
+            && hooks.tracer.is_none()
+            && hooks.profiler.is_none()
+            && hooks.dump.is_none()
+            && registers.token_index == 0
+            && jit::supported::<C>(config)
+        {
+            if let Some(program) = jit::Program::compile::<C>(tokens, config, tape.size()) {
+                return program.run(config, tokens, tape, registers, input, output, source);
+            }
+        }
+        if registers.input_ended {
+            input = None;
+        }
+        let deadline = config.timeout.map(|timeout| Instant::now() + timeout);
+        let mut run_steps = 0u64;
+        let mut stderr = io::stderr();
+        let dump: &mut dyn Write = match &mut hooks.dump {
+            Some(writer) => writer,
+            None => &mut stderr,
+        };
+
+        while let Some(token) = tokens.get(registers.token_index) {
+            if budget.is_some_and(|budget| run_steps >= budget) {
+                output.flush().map_err(InterpreterError::IoError)?;
+                return Ok(Status::Paused);
+            }
+            if config
+                .max_steps
+                .is_some_and(|max_steps| registers.steps >= max_steps)
+            {
+                return Err(InterpreterError::StepLimitReached(
+                    registers.steps,
+                    token.index_file(),
+                ));
+            }
+            // Reading the clock is slow, it is only checked every few steps
+            if run_steps.is_multiple_of(DEADLINE_CHECK_STEPS)
+                && deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return Err(InterpreterError::TimeoutReached(
+                    registers.steps,
+                    token.index_file(),
+                ));
+            }
+            registers.steps += 1;
+            run_steps += 1;
+            let index = registers.token_index;
+            let written = match hooks.tracer {
+                Some(_) => Tracer::written(token, tape),
+                None => Vec::new(),
+            };
+            let started = hooks.profiler.as_ref().map(|_| Instant::now());
+
+            match token {
+                Command::Loop(LoopOptions::PointerStart(Some(pointer)), _) => {
+                    if tape.get().is_zero() {
+                        registers.token_index = *pointer;
+                    }
+                }
+                Command::Loop(LoopOptions::PointerEnd(Some(pointer)), _) => {
+                    if !tape.get().is_zero() {
+                        registers.token_index = *pointer;
+                    }
+                }
+                token => Self::execute_token(
+                    config, token, tape, registers, &mut input, output, dump, source,
+                )?,
+            }
+            if let Some(tracer) = &mut hooks.tracer {
+                tracer.record(index, token, tape, &written)?;
+            }
+            if let (Some(profiler), Some(started)) = (&mut hooks.profiler, started) {
+                profiler.record(index, started.elapsed());
+            }
+
+            registers.token_index += 1;
+        }
+
+        output.flush().map_err(InterpreterError::IoError)?;
+        Ok(Status::Halted)
+    }
+
+    // Line printed by `#`, the current cell is in brackets. With the source code the position is `line:column`.
+    fn dump<C: Cell>(tape: &Tape<C>, index_file: usize, source: Option<&SourceMap>) -> String {
+        let cells: Vec<String> = tape
+            .window(DUMP_RADIUS)
+            .into_iter()
+            .map(|(position, value)| {
+                if position == tape.position() {
+                    format!("[{}:{}]", position, value)
+                } else {
+                    format!("{}:{}", position, value)
+                }
+            })
+            .collect();
+        let location = match source {
+            Some(source) => source.position(index_file).to_string(),
+            None => index_file.to_string(),
+        };
+        format!(
+            "# code position `{}`, memory cell `{}`: {}",
+            location,
+            tape.position(),
+            cells.join(" ")
+        )
+    }
+
+    // Run a command that does not jump, the loops with a body are handled by the caller
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn execute_token<C: Cell>(
+        config: &Config,
+        token: &Command,
+        tape: &mut Tape<C>,
+        registers: &mut Registers,
+        input: &mut Option<&mut dyn Read>,
+        output: &mut dyn Write,
+        dump: &mut dyn Write,
+        source: Option<&SourceMap>,
+    ) -> Result<(), InterpreterError> {
+        // Match each command and perform the corresponding operation
+        match token {
+            Command::Add(increment, offset, index_file) => {
+                let index = tape
+                    .index_at(*offset)
+                    .ok_or(InterpreterError::PointerOutOfBounds(
+                        *index_file,
+                        tape.boundary(*offset),
+                    ))?;
+                let value = config.overflow.add(
+                    tape.get_at(index),
+                    *increment,
+                    *index_file,
+                    tape.position_at(*offset),
+                )?;
+                tape.set_at(index, value);
+            }
+            Command::Set(value, offset, index_file) => {
+                let index = tape
+                    .index_at(*offset)
+                    .ok_or(InterpreterError::PointerOutOfBounds(
+                        *index_file,
+                        tape.boundary(*offset),
+                    ))?;
+                let value = config.overflow.add(
+                    &C::default(),
+                    *value,
+                    *index_file,
+                    tape.position_at(*offset),
+                )?;
+                tape.set_at(index, value);
+            }
+            Command::Move(pointer, index_file) => {
+                tape.move_pointer(*pointer)
+                    .ok_or(InterpreterError::PointerOutOfBounds(
+                        *index_file,
+                        tape.boundary(*pointer),
+                    ))?
+            }
+            Command::Buffer(BufferOptions::Input, offset, index_file) => {
+                let index = tape
+                    .index_at(*offset)
+                    .ok_or(InterpreterError::PointerOutOfBounds(
+                        *index_file,
+                        tape.boundary(*offset),
+                    ))?;
+                let value = match input.as_mut() {
+                    Some(reader) => {
+                        // The program may be waiting for the user, show the pending output
+                        output.flush().map_err(InterpreterError::IoError)?;
+                        Self::read_byte(*reader)?
+                    }
+                    None => None,
+                };
+                match (value, config.eof) {
+                    (Some(value), _) => tape.set_at(index, C::from_byte(value)),
+                    // EOF
+                    (None, EofPolicy::Unchanged) => {}
+                    (None, EofPolicy::Zero) => tape.set_at(index, C::default()),
+                    (None, EofPolicy::MaxValue) => tape.set_at(index, C::max_value()),
+                    (None, EofPolicy::ZeroThenError) if input.is_some() => {
+                        tape.set_at(index, C::default());
+                        *input = None;
+                        registers.input_ended = true;
+                    }
+                    (None, EofPolicy::ZeroThenError | EofPolicy::Error) => {
+                        return Err(InterpreterError::MissingArgs)
+                    }
+                }
+            }
+            Command::Buffer(BufferOptions::Output, offset, index_file) => {
+                let index = tape
+                    .index_at(*offset)
+                    .ok_or(InterpreterError::PointerOutOfBounds(
+                        *index_file,
+                        tape.boundary(*offset),
+                    ))?;
+                output
+                    .write_all(&[tape.get_at(index).to_byte()])
+                    .map_err(InterpreterError::IoError)?
+            }
+            Command::Dump(index_file) => {
+                writeln!(dump, "{}", Self::dump(tape, *index_file, source))
+                    .map_err(InterpreterError::IoError)?
+            }
+            Command::Loop(LoopOptions::PointerStart(None), _)
+            | Command::Loop(LoopOptions::PointerEnd(None), _) => {
+                return Err(InterpreterError::UnconnectedLoops)
+            }
+            Command::Loop(LoopOptions::Comment, index_file) => {
+                if !tape.get().is_zero() {
+                    return Err(InterpreterError::InfinityLoopFound(
+                        *index_file,
+                        tape.get().to_string(),
+                        tape.position(),
+                    ));
+                }
+            }
+            Command::Loop(LoopOptions::AddToReset(value), index_file) => {
+                // A step that is a multiple of 2^bits never reaches 0, but the loop is not entered
+                if tape.get().is_zero() {
+                    return Ok(());
+                }
+                if config.overflow == OverflowPolicy::Error
+                    && C::BOUNDED
+                    && !tape.get().checked_reaches_zero(*value)
+                {
+                    return Err(InterpreterError::CellOverflow(*index_file, tape.position()));
+                }
+                // Iterating could take 2^32 steps, or never end with unbounded cells
+                if !tape.get().reaches_zero(*value) {
+                    return Err(InterpreterError::InfinityLoopFound(
+                        *index_file,
+                        tape.get().to_string(),
+                        tape.position(),
+                    ));
+                }
+                tape.set(C::default());
+            }
+            Command::Loop(LoopOptions::MoveToCell(pointer), index_file) => {
+                tape.scan_zero(*pointer).map_err(|failure| match failure {
+                    ScanFailure::OutOfBounds => {
+                        InterpreterError::PointerOutOfBounds(*index_file, tape.boundary(*pointer))
+                    }
+                    ScanFailure::MemoryFull => {
+                        InterpreterError::InfinityLoopMemoryFull(*index_file)
+                    }
+                    ScanFailure::Cycle => {
+                        InterpreterError::InfinityLoopMovement(*index_file, *pointer)
+                    }
+                })?
+            }
+            Command::Loop(LoopOptions::CutAdd(pointer, value_1, value_2), index_file) => {
+                if tape.get().is_zero() {
+                    return Ok(());
+                }
+                let Some(pointer_momevent) = tape.index_at(*pointer) else {
+                    let updates = [(0, *value_1), (*pointer, *value_2)];
+                    return Err(Self::out_of_bounds(tape, config, &updates, *index_file));
+                };
+                let updates = [
+                    (tape.pointer(), 0, *value_1),
+                    (pointer_momevent, *pointer, *value_2),
+                ];
+                Self::add_until_zero(tape, config, &updates, *index_file)?;
+            }
+            Command::Loop(LoopOptions::MultiplyAdd(updates), index_file) => {
+                if !tape.get().is_zero() {
+                    // Growing the tape to the left moves the cells, the indexes are taken once every cell exists
+                    for (offset, _) in updates {
+                        if tape.index_at(*offset).is_none() {
+                            return Err(Self::out_of_bounds(tape, config, updates, *index_file));
                         }
                     }
+                    let mut indexes: Vec<(usize, isize, i64)> = updates
+                        .iter()
+                        .filter_map(|(offset, factor)| {
+                            Some((tape.index_at(*offset)?, *offset, *factor))
+                        })
+                        .collect();
+                    // The counter goes first, in a small cyclic tape a cell can be the counter itself
+                    indexes.insert(0, (tape.pointer(), 0, -1));
+                    Self::add_until_zero(tape, config, &indexes, *index_file)?;
+                }
+            }
+            Command::Loop(
+                LoopOptions::PointerStart(Some(_)) | LoopOptions::PointerEnd(Some(_)),
+                _,
+            ) => {}
+        }
+
+        Ok(())
+    }
+
+    // Error of the first iteration of a loop whose body leaves the tape, with checked arithmetic a cell
+    // updated before could overflow first
+    fn out_of_bounds<C: Cell>(
+        tape: &mut Tape<C>,
+        config: &Config,
+        updates: &[(isize, i64)],
+        index_file: usize,
+    ) -> InterpreterError {
+        for (offset, factor) in updates {
+            let Some(index) = tape.index_at(*offset) else {
+                return InterpreterError::PointerOutOfBounds(index_file, tape.boundary(*offset));
+            };
+            let position = tape.position_at(*offset);
+            if let Err(error) =
+                config
+                    .overflow
+                    .add(tape.get_at(index), *factor, index_file, position)
+            {
+                return error;
+            }
+        }
+        InterpreterError::PointerOutOfBounds(index_file, tape.position())
+    }
 
-                    token_index += 1;
+    // Loop whose body adds `factor` to the cells at `index` (`offset` from the pointer) until the current cell is 0
+    fn add_until_zero<C: Cell>(
+        tape: &mut Tape<C>,
+        config: &Config,
+        updates: &[(usize, isize, i64)],
+        index_file: usize,
+    ) -> Result<(), InterpreterError> {
+        let step: i64 = updates
+            .iter()
+            .filter(|(index, ..)| *index == tape.pointer())
+            .map(|(.., factor)| factor)
+            .sum();
+        let infinity_loop = |tape: &Tape<C>| {
+            InterpreterError::InfinityLoopFound(index_file, tape.get().to_string(), tape.position())
+        };
+        match config.overflow {
+            OverflowPolicy::Error if C::BOUNDED => {
+                return Self::checked_add_until_zero(tape, updates, index_file, step)
+            }
+            // The loop runs `n` times, where `n` is the first solution of `value + n * step = 0`,
+            // unbounded cells never overflow
+            OverflowPolicy::Wrap | OverflowPolicy::Error => {
+                let iterations = tape.get().steps_to_zero(step).ok_or(infinity_loop(tape))?;
+                for (index, _, factor) in updates {
+                    if *index != tape.pointer() {
+                        tape.set_at(
+                            *index,
+                            tape.get_at(*index).multiply_add(*factor, &iterations),
+                        );
+                    }
                 }
+                tape.set(C::default());
+            }
+        }
+        Ok(())
+    }
 
-                self.output
-                    .set(output)
-                    .map_err(|_| InterpreterError::OutputOverwritten)?;
+    // `add_until_zero` with bounded cells that must not overflow. After update `j` of iteration `k` a cell holds
+    // `value + partial + (k - 1) * total`, where `partial` adds its factors up to `j` and `total` all of them,
+    // so the first update leaving the limits is found without iterating
+    fn checked_add_until_zero<C: Cell>(
+        tape: &mut Tape<C>,
+        updates: &[(usize, isize, i64)],
+        index_file: usize,
+        step: i64,
+    ) -> Result<(), InterpreterError> {
+        let max = i128::try_from(&C::max_value().to_bigint()).unwrap_or(i128::MAX);
+        let value = |index: usize| i128::try_from(&tape.get_at(index).to_bigint()).unwrap_or(0);
+        let increment = |updates: &[(usize, isize, i64)], index: usize| -> i128 {
+            updates
+                .iter()
+                .filter(|(other, ..)| *other == index)
+                .map(|(.., factor)| i128::from(*factor))
+                .sum()
+        };
+        // The counter is checked after each iteration
+        let (counter, step) = (value(tape.pointer()), i128::from(step));
+        let iterations = (step < 0 && counter % step == 0).then(|| -counter / step);
+        // Iteration and update of the first overflow, ties go to the first update of the body
+        let mut overflow: Option<(i128, usize)> = None;
+        for (update, (index, ..)) in updates.iter().enumerate() {
+            let after = value(*index) + increment(&updates[..=update], *index);
+            let total = increment(updates, *index);
+            let iteration = match total {
+                _ if !(0..=max).contains(&after) => 1,
+                1.. => (max - after) / total + 2,
+                ..=-1 => after / -total + 2,
+                0 => continue,
+            };
+            if iterations.is_none_or(|iterations| iteration <= iterations)
+                && overflow.is_none_or(|(first, _)| iteration < first)
+            {
+                overflow = Some((iteration, update));
             }
-            None => return Err(InterpreterError::TokensUnknown),
         }
+        if let Some((_, update)) = overflow {
+            return Err(InterpreterError::CellOverflow(
+                index_file,
+                tape.position_at(updates[update].1),
+            ));
+        }
+        let Some(iterations) = iterations else {
+            return Err(InterpreterError::InfinityLoopFound(
+                index_file,
+                tape.get().to_string(),
+                tape.position(),
+            ));
+        };
+        let values: Vec<(usize, i128)> = updates
+            .iter()
+            .map(|(index, ..)| {
+                (
+                    *index,
+                    value(*index) + iterations * increment(updates, *index),
+                )
+            })
+            .collect();
+        for (index, value) in values {
+            // Every value is inside the limits of the cell
+            tape.set_at(index, C::default().add(value as i64));
+        }
+        Ok(())
+    }
+
+    // Run the loaded program until it halts
+    pub fn run(
+        &mut self,
+        input: Option<&mut dyn Read>,
+        output: &mut dyn Write,
+    ) -> Result<Status, InterpreterError> {
+        self.run_code(input, output, None)
+    }
+
+    // Run at most `steps` commands of the loaded program, the next call resumes it
+    pub fn run_steps(
+        &mut self,
+        steps: u64,
+        input: Option<&mut dyn Read>,
+        output: &mut dyn Write,
+    ) -> Result<Status, InterpreterError> {
+        self.run_code(input, output, Some(steps))
+    }
+
+    // Run the loaded program from the start with in-memory input, the output is kept in the interpreter
+    pub fn run_with_args(&mut self, args: Option<Data>) -> Result<(), InterpreterError> {
+        self.reset();
+        let mut output: Data = match &self.tokens {
+            // It is an initial value; the true one is unknown because it could be within a loop, hence it could be greater (if it repeats any loop) or smaller (if it didn't enter any loop).
+            Some(tokens) => Vec::with_capacity(Command::token_counter(
+                tokens,
+                Command::Buffer(BufferOptions::Output, 0, 0),
+            )),
+            None => return Err(InterpreterError::TokensUnknown),
+        };
+        let mut args: Option<&[u8]> = args.as_deref();
+        self.run(args.as_mut().map(|a| a as &mut dyn Read), &mut output)?;
+        self.output = Some(output);
 
         Ok(())
     }
 
+    // Execute with in-memory input and output, the output is kept in the interpreter
     pub fn execute(
         &mut self,
         tokens: Commands,
         args: Option<Data>,
     ) -> Result<(), InterpreterError> {
-        if let Some(bf_args) = args {
-            self.set_args(bf_args)?;
-        }
-        self.set_tokens(tokens)?;
-        self.run_code()
+        self.load(tokens);
+        self.run_with_args(args)
+    }
+
+    // Execute reading `,` from `input` and writing `.` to `output` as the program runs
+    pub fn execute_io(
+        &mut self,
+        tokens: Commands,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> Result<(), InterpreterError> {
+        self.load(tokens);
+        self.run(Some(input), output)?;
+
+        Ok(())
     }
 
     pub fn get_output_as_vec(&self) -> Result<Data, InterpreterError> {
-        match self.output.get() {
+        match &self.output {
             Some(output) => Ok(output.clone()),
             None => Err(InterpreterError::OutputUnknown),
         }
     }
 
     pub fn get_output_as_string(&self) -> Result<String, InterpreterError> {
-        match self.output.get() {
+        match &self.output {
             Some(output) => Ok(String::from_utf8_lossy(output.as_slice()).to_string()),
             None => Err(InterpreterError::OutputUnknown),
         }
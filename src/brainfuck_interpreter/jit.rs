@@ -0,0 +1,448 @@
+use super::{
+    cell::Cell,
+    command::{Command, LoopOptions},
+    config::{Config, OverflowPolicy},
+    error::InterpreterError,
+    interpreter::{Interpreter, Registers, Status},
+    source::SourceMap,
+    tape::{Tape, TapePolicy},
+};
+use std::{
+    ffi::c_void,
+    io::{Read, Write},
+    mem::size_of,
+    ptr,
+};
+
+// x86-64 machine code of the optimized instructions. Adds, sets, moves and loops run natively, the other
+// commands call back `Interpreter::execute_token` so the errors and the EOF policy are the same.
+//
+// Registers while the program runs:
+//   rbx: first cell of the tape    r12: pointer         r13: number of cells
+//   r14: address of `callback`     r15: `Context`       rbp: steps left before the limit
+
+// The tape can not grow: the machine code keeps the address of the cells. The time limit needs the clock.
+pub(super) fn supported<C: Cell>(config: &Config) -> bool {
+    config.jit
+        && C::BOUNDED
+        && matches!(size_of::<C>(), 1 | 2 | 4)
+        && matches!(config.tape, TapePolicy::Wrap | TapePolicy::Error)
+        && config.timeout.is_none()
+}
+
+// State shared with the callbacks, `remaining` is read and written by the machine code
+#[repr(C)]
+struct Context<'a, C: Cell> {
+    remaining: u64,
+    config: &'a Config,
+    tokens: &'a [Command],
+    tape: &'a mut Tape<C>,
+    registers: &'a mut Registers,
+    input: Option<&'a mut dyn Read>,
+    output: &'a mut dyn Write,
+    source: Option<&'a SourceMap>,
+    error: Option<(usize, InterpreterError)>, // token that failed
+}
+
+// Run a command with the interpreter, it returns the new pointer or -1 when it fails
+extern "C" fn callback<C: Cell>(context: *mut Context<C>, pointer: usize, token: usize) -> isize {
+    // SAFETY: the machine code passes the context it received from `Program::run`
+    let context = unsafe { &mut *context };
+    context.tape.set_pointer(pointer);
+    // The program is not compiled when the dumps of `#` have another writer
+    let result = Interpreter::execute_token(
+        context.config,
+        &context.tokens[token],
+        context.tape,
+        context.registers,
+        &mut context.input,
+        context.output,
+        &mut std::io::stderr(),
+        context.source,
+    );
+    match result {
+        Ok(()) => context.tape.pointer() as isize,
+        Err(error) => {
+            context.error = Some((token, error));
+            -1
+        }
+    }
+}
+
+// The command `token` would exceed the step limit
+extern "C" fn step_limit<C: Cell>(context: *mut Context<C>, pointer: usize, token: usize) -> isize {
+    // SAFETY: the machine code passes the context it received from `Program::run`
+    let context = unsafe { &mut *context };
+    context.tape.set_pointer(pointer);
+    let error = InterpreterError::StepLimitReached(
+        context.config.max_steps.unwrap_or(u64::MAX),
+        context.tokens[token].index_file(),
+    );
+    context.error = Some((token, error));
+    -1
+}
+
+struct Assembler {
+    code: Vec<u8>,
+    size: usize,                 // bytes of a cell
+    errors: Vec<usize>,          // jumps to the exit after a callback error
+    limits: Vec<(usize, usize)>, // jumps to the step limit of a token
+}
+
+impl Assembler {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn here(&self) -> usize {
+        self.code.len()
+    }
+
+    // Jump with a 32 bits displacement, the position of the displacement is returned to patch it
+    fn jump(&mut self, opcode: &[u8]) -> usize {
+        self.bytes(opcode);
+        self.bytes(&[0; 4]);
+        self.here() - 4
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        let displacement = target as i32 - (at + 4) as i32;
+        self.code[at..at + 4].copy_from_slice(&displacement.to_le_bytes());
+    }
+
+    // Value truncated to the width of a cell
+    fn immediate(&mut self, value: i64) {
+        self.code
+            .extend_from_slice(&value.to_le_bytes()[..self.size]);
+    }
+
+    // Byte offset of the cell at `offset` from the pointer, `None` if it does not fit in a displacement
+    fn displacement(&self, offset: isize) -> Option<i32> {
+        i32::try_from(offset.checked_mul(self.size as isize)?).ok()
+    }
+
+    // Instruction on `[rbx + r12 * size + displacement]`, `opcodes` for 8 bits and for 16/32 bits,
+    // `reg` is a register or the opcode extension
+    fn cell(&mut self, opcodes: [u8; 2], reg: u8, displacement: i32) {
+        let opcode = match self.size {
+            1 => opcodes[0],
+            _ => opcodes[1],
+        };
+        if self.size == 2 {
+            self.bytes(&[0x66]);
+        }
+        self.memory(&[opcode], reg, displacement);
+    }
+
+    // REX.X, opcode, ModRM and SIB of `[rbx + r12 * size + displacement]`
+    fn memory(&mut self, opcode: &[u8], reg: u8, displacement: i32) {
+        let scale = self.size.trailing_zeros() as u8;
+        let mode = match displacement {
+            0 => 0b00,
+            _ => 0b10,
+        };
+        self.bytes(&[0x42]);
+        self.bytes(opcode);
+        self.bytes(&[
+            mode << 6 | reg << 3 | 0b100,
+            scale << 6 | 0b100 << 3 | 0b011,
+        ]);
+        if displacement != 0 {
+            self.bytes(&displacement.to_le_bytes());
+        }
+    }
+
+    // `cmp [current cell], 0`
+    fn test_current(&mut self) {
+        self.cell([0x80, 0x83], 7, 0);
+        self.bytes(&[0]);
+    }
+
+    // Jump to the returned position when the cell at `offset` is outside the tape
+    fn check(&mut self, offset: isize) -> usize {
+        self.bytes(&[0x49, 0x8d, 0x84, 0x24]); // lea rax, [r12 + offset]
+        self.bytes(&(offset as i32).to_le_bytes());
+        self.bytes(&[0x4c, 0x39, 0xe8]); // cmp rax, r13
+        self.jump(&[0x0f, 0x83]) // jae outside
+    }
+
+    // Call `rax`/`r14` with the context, the pointer and `token`
+    fn call(&mut self, token: usize, limit: bool) {
+        self.bytes(&[0x4c, 0x89, 0xff]); // mov rdi, r15
+        self.bytes(&[0x4c, 0x89, 0xe6]); // mov rsi, r12
+        self.bytes(&[0x48, 0xba]); // mov rdx, token
+        self.bytes(&(token as u64).to_le_bytes());
+        if limit {
+            self.bytes(&[0xff, 0xd0]); // call rax
+        } else {
+            self.bytes(&[0x41, 0xff, 0xd6]); // call r14
+        }
+    }
+
+    // The interpreter runs the command, a negative pointer is an error
+    fn callback(&mut self, token: usize) {
+        self.call(token, false);
+        self.bytes(&[0x48, 0x85, 0xc0]); // test rax, rax
+        let error = self.jump(&[0x0f, 0x88]); // js error
+        self.errors.push(error);
+        self.bytes(&[0x49, 0x89, 0xc4]); // mov r12, rax
+    }
+
+    // `fast` runs when every cell at `offsets` is in the tape, otherwise the interpreter runs the command
+    fn checked(&mut self, offsets: &[isize], token: usize, fast: impl FnOnce(&mut Self)) {
+        if offsets
+            .iter()
+            .any(|offset| self.displacement(*offset).is_none())
+        {
+            return self.callback(token);
+        }
+        let outside: Vec<usize> = offsets
+            .iter()
+            .filter(|offset| **offset != 0)
+            .map(|offset| self.check(*offset))
+            .collect();
+        fast(self);
+        if outside.is_empty() {
+            return;
+        }
+        let done = self.jump(&[0xe9]); // jmp done
+        for at in outside {
+            self.patch(at, self.here());
+        }
+        self.callback(token);
+        self.patch(done, self.here());
+    }
+
+    // Instruction with an immediate on the cell at `offset`
+    fn update(&mut self, opcodes: [u8; 2], offset: isize, value: i64, token: usize) {
+        self.checked(&[offset], token, |assembler| {
+            let displacement = assembler.displacement(offset).unwrap_or_default();
+            assembler.cell(opcodes, 0, displacement);
+            assembler.immediate(value);
+        });
+    }
+
+    // `[-1 and adds at offsets]`: each cell at an offset is increased by its factor times the current cell
+    fn multiply_add(&mut self, updates: &[(isize, i64)], token: usize) {
+        if updates.iter().any(|(offset, _)| *offset == 0) {
+            return self.callback(token);
+        }
+        self.test_current();
+        let zero = self.jump(&[0x0f, 0x84]); // je done
+        let offsets: Vec<isize> = updates.iter().map(|(offset, _)| *offset).collect();
+        self.checked(&offsets, token, |assembler| {
+            let load: &[u8] = match assembler.size {
+                1 => &[0x0f, 0xb6], // movzx ecx, byte
+                2 => &[0x0f, 0xb7], // movzx ecx, word
+                _ => &[0x8b],       // mov ecx, dword
+            };
+            assembler.memory(load, 1, 0);
+            for (offset, factor) in updates {
+                assembler.bytes(&[0x69, 0xd1]); // imul edx, ecx, factor
+                assembler.bytes(&(*factor as i32).to_le_bytes());
+                let displacement = assembler.displacement(*offset).unwrap_or_default();
+                assembler.cell([0x00, 0x01], 2, displacement); // add [cell], edx
+            }
+            assembler.cell([0xc6, 0xc7], 0, 0); // mov [current cell], 0
+            assembler.immediate(0);
+        });
+        self.patch(zero, self.here());
+    }
+}
+
+// Executable memory with the compiled program
+pub(super) struct Program {
+    memory: *mut c_void,
+    len: usize,
+}
+
+impl Program {
+    // Machine code for `tokens` on a tape of `cells` cells, `None` if the loops are not connected or the
+    // memory can not be made executable
+    pub(super) fn compile<C: Cell>(
+        tokens: &[Command],
+        config: &Config,
+        cells: usize,
+    ) -> Option<Self> {
+        let checked = config.overflow == OverflowPolicy::Error;
+        let mut assembler = Assembler {
+            code: Vec::with_capacity(tokens.len() * 16),
+            size: size_of::<C>(),
+            errors: Vec::new(),
+            limits: Vec::new(),
+        };
+        // Callee-saved registers, the stack stays aligned to 16 bytes for the calls
+        assembler.bytes(&[0x53, 0x55, 0x41, 0x54, 0x41, 0x55, 0x41, 0x56, 0x41, 0x57]); // push rbx, rbp, r12-r15
+        assembler.bytes(&[0x48, 0x83, 0xec, 0x08]); // sub rsp, 8
+        assembler.bytes(&[0x49, 0x89, 0xff]); // mov r15, rdi
+        assembler.bytes(&[0x48, 0x89, 0xf3]); // mov rbx, rsi
+        assembler.bytes(&[0x49, 0x89, 0xd4]); // mov r12, rdx
+        assembler.bytes(&[0x49, 0xbd]); // mov r13, cells
+        assembler.bytes(&(cells as u64).to_le_bytes());
+        assembler.bytes(&[0x49, 0xbe]); // mov r14, callback
+        assembler.bytes(&(callback::<C> as *const () as u64).to_le_bytes());
+        assembler.bytes(&[0x49, 0x8b, 0x2f]); // mov rbp, [r15]
+
+        // Start of each open loop: its index, the index of its end, the jump to patch and its body
+        let mut loops: Vec<(usize, usize, usize, usize)> = Vec::new();
+        for (index, token) in tokens.iter().enumerate() {
+            assembler.bytes(&[0x48, 0x83, 0xed, 0x01]); // sub rbp, 1
+            let limit = assembler.jump(&[0x0f, 0x82]); // jb limit
+            assembler.limits.push((limit, index));
+
+            match token {
+                Command::Add(value, offset, _) if !checked => {
+                    assembler.update([0x80, 0x81], *offset, *value, index)
+                }
+                Command::Set(value, offset, _) if !checked => {
+                    assembler.update([0xc6, 0xc7], *offset, *value, index)
+                }
+                // An odd step reaches 0 from any value
+                Command::Loop(LoopOptions::AddToReset(step), _) if !checked && step % 2 != 0 => {
+                    assembler.update([0xc6, 0xc7], 0, 0, index)
+                }
+                Command::Move(0, _) => {}
+                Command::Move(offset, _) => assembler.checked(&[*offset], index, |assembler| {
+                    assembler.bytes(&[0x49, 0x89, 0xc4]); // mov r12, rax
+                }),
+                Command::Loop(LoopOptions::MultiplyAdd(updates), _) if !checked => {
+                    assembler.multiply_add(updates, index)
+                }
+                Command::Loop(LoopOptions::PointerStart(Some(end)), _) => {
+                    assembler.test_current();
+                    let exit = assembler.jump(&[0x0f, 0x84]); // je end
+                    loops.push((index, *end, exit, assembler.here()));
+                }
+                Command::Loop(LoopOptions::PointerEnd(Some(start)), _) => {
+                    let (open, end, exit, body) = loops.pop()?;
+                    if (open, end) != (*start, index) {
+                        return None;
+                    }
+                    assembler.test_current();
+                    let repeat = assembler.jump(&[0x0f, 0x85]); // jne body
+                    assembler.patch(repeat, body);
+                    assembler.patch(exit, assembler.here());
+                }
+                _ => assembler.callback(index),
+            }
+        }
+        if !loops.is_empty() {
+            return None;
+        }
+
+        assembler.bytes(&[0x4c, 0x89, 0xe0]); // mov rax, r12
+        let error = assembler.here();
+        assembler.bytes(&[0x49, 0x89, 0x2f]); // mov [r15], rbp
+        assembler.bytes(&[0x48, 0x83, 0xc4, 0x08]); // add rsp, 8
+        assembler.bytes(&[0x41, 0x5f, 0x41, 0x5e, 0x41, 0x5d, 0x41, 0x5c, 0x5d, 0x5b]); // pop r15-r12, rbp, rbx
+        assembler.bytes(&[0xc3]); // ret
+        for at in std::mem::take(&mut assembler.errors) {
+            assembler.patch(at, error);
+        }
+        // The step limit is reached without running the command, no step is left
+        for (at, token) in std::mem::take(&mut assembler.limits) {
+            assembler.patch(at, assembler.here());
+            assembler.bytes(&[0x31, 0xed]); // xor ebp, ebp
+            assembler.bytes(&[0x48, 0xb8]); // mov rax, step_limit
+            assembler.bytes(&(step_limit::<C> as *const () as u64).to_le_bytes());
+            assembler.call(token, true);
+            let exit = assembler.jump(&[0xe9]); // jmp error
+            assembler.patch(exit, error);
+        }
+
+        Self::load(&assembler.code)
+    }
+
+    fn load(code: &[u8]) -> Option<Self> {
+        // SAFETY: a new private mapping is written and then only executed
+        unsafe {
+            let memory = libc::mmap(
+                ptr::null_mut(),
+                code.len(),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if memory == libc::MAP_FAILED {
+                return None;
+            }
+            let program = Self {
+                memory,
+                len: code.len(),
+            };
+            ptr::copy_nonoverlapping(code.as_ptr(), memory as *mut u8, code.len());
+            match libc::mprotect(memory, code.len(), libc::PROT_READ | libc::PROT_EXEC) {
+                0 => Some(program),
+                _ => None,
+            }
+        }
+    }
+
+    // Run the program from the start, like `Interpreter::run_tape`
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn run<C: Cell>(
+        &self,
+        config: &Config,
+        tokens: &[Command],
+        tape: &mut Tape<C>,
+        registers: &mut Registers,
+        input: Option<&mut dyn Read>,
+        output: &mut dyn Write,
+        source: Option<&SourceMap>,
+    ) -> Result<Status, InterpreterError> {
+        let max_steps = config.max_steps.unwrap_or(u64::MAX);
+        let cells = tape.as_mut_ptr() as *mut c_void;
+        let pointer = tape.pointer();
+        let input = if registers.input_ended {
+            None
+        } else {
+            input.map(|input| input as &mut dyn Read)
+        };
+        let mut context = Context {
+            remaining: max_steps - registers.steps,
+            config,
+            tokens,
+            tape,
+            registers,
+            input,
+            output,
+            source,
+            error: None,
+        };
+        // SAFETY: the code follows the System V calling convention and only touches the cells of the tape,
+        // which does not grow
+        let result = unsafe {
+            let entry: extern "C" fn(*mut c_void, *mut c_void, usize) -> isize =
+                std::mem::transmute(self.memory);
+            entry(
+                &mut context as *mut Context<C> as *mut c_void,
+                cells,
+                pointer,
+            )
+        };
+
+        context.registers.steps = max_steps - context.remaining;
+        match context.error.take() {
+            Some((token, error)) => {
+                context.registers.token_index = token;
+                Err(error)
+            }
+            None => {
+                context.tape.set_pointer(result as usize);
+                context.registers.token_index = tokens.len();
+                context.output.flush().map_err(InterpreterError::IoError)?;
+                Ok(Status::Halted)
+            }
+        }
+    }
+}
+
+impl Drop for Program {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was created by `Program::load` and is not used after the program
+        unsafe {
+            libc::munmap(self.memory, self.len);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use super::{
+    command::{Command, Commands},
+    config::Config,
+    error::InterpreterError,
+};
+use std::{fmt, str::FromStr};
+
+// Rewrite of the instructions, the enabled passes run in the order of `Pass::ALL`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pass {
+    Fusion,    // adds, sets and I/O at an offset from the pointer, a single move per basic block
+    Loops,     // common loops as a single instruction
+    DeadLoops, // loops that never run are removed
+    ConstantFold, // cells with a known value are set, loops that never end are found before running
+}
+
+impl Pass {
+    pub const ALL: [Pass; 4] = [
+        Self::Fusion,
+        Self::Loops,
+        Self::DeadLoops,
+        Self::ConstantFold,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Fusion => "fusion",
+            Self::Loops => "loops",
+            Self::DeadLoops => "dead",
+            Self::ConstantFold => "fold",
+        }
+    }
+
+    fn run(&self, tokens: &[Command], config: &Config) -> Result<Commands, InterpreterError> {
+        match self {
+            Self::Fusion => Ok(Command::add_move_reduce_tokens(tokens, config)),
+            Self::Loops => Ok(Command::loop_reduce_tokens(tokens, config)),
+            Self::DeadLoops => Ok(Command::dead_loop_tokens(tokens, config)),
+            Self::ConstantFold => Command::constant_fold_tokens(tokens, config),
+        }
+    }
+}
+
+impl FromStr for Pass {
+    type Err = InterpreterError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match Self::ALL.into_iter().find(|pass| pass.name() == name) {
+            Some(pass) => Ok(pass),
+            None => Err(InterpreterError::InvalidOption(
+                "pass".to_string(),
+                name.to_string(),
+            )),
+        }
+    }
+}
+
+// Groups of passes, like the `-O` options of a compiler
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OptLevel {
+    O0, // no rewrites, every command is executed as it is written
+    O1, // fusion and loops
+    #[default]
+    O2, // every pass
+}
+
+impl OptLevel {
+    pub fn passes(&self) -> Vec<Pass> {
+        match self {
+            Self::O0 => Vec::new(),
+            Self::O1 => vec![Pass::Fusion, Pass::Loops],
+            Self::O2 => Pass::ALL.to_vec(),
+        }
+    }
+}
+
+impl FromStr for OptLevel {
+    type Err = InterpreterError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "0" => Ok(Self::O0),
+            "1" => Ok(Self::O1),
+            "2" => Ok(Self::O2),
+            _ => Err(InterpreterError::InvalidOption(
+                "-O".to_string(),
+                name.to_string(),
+            )),
+        }
+    }
+}
+
+// Instructions before and after a pass
+#[derive(Clone, Debug)]
+pub struct PassStats {
+    pub pass: Pass,
+    pub before: usize,
+    pub after: usize,
+}
+
+impl fmt::Display for PassStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<8}{:>8} -> {:<8}{} removed",
+            self.pass.name(),
+            self.before,
+            self.after,
+            self.before as isize - self.after as isize
+        )
+    }
+}
+
+// Run the passes enabled in `config`, each one reads the instructions of the previous one
+pub fn optimize(
+    tokens: Commands,
+    config: &Config,
+) -> Result<(Commands, Vec<PassStats>), InterpreterError> {
+    let mut tokens = tokens;
+    let mut stats = Vec::with_capacity(config.passes.len());
+
+    for pass in Pass::ALL.iter().filter(|pass| config.passes.contains(pass)) {
+        let before = tokens.len();
+        tokens = pass.run(&tokens, config)?;
+        stats.push(PassStats {
+            pass: *pass,
+            before,
+            after: tokens.len(),
+        });
+    }
+
+    Ok((tokens, stats))
+}
@@ -0,0 +1,419 @@
+use super::{
+    cell::{Cell, CellWidth},
+    command::{Command, CommandClassic, ParseMode},
+    config::{Config, EofPolicy},
+    error::InterpreterError,
+    interpreter::{Data, Interpreter},
+    tape::Tape,
+};
+use num_bigint::BigInt;
+use std::{io::Read, mem::discriminant};
+
+// Final state of a run, used to compare the optimized interpreter with the reference one
+#[derive(Debug)]
+pub struct Outcome {
+    pub output: Data,
+    pub result: Result<(), InterpreterError>,
+    pub pointer: isize,
+    pub cells: Vec<(isize, String)>, // cell number and value of the cells different from 0
+}
+
+// Result of comparing the optimized interpreter with the reference interpreter
+#[derive(Debug)]
+pub enum Verdict {
+    Match,
+    Inconclusive, // the reference interpreter reached the step limit, the program may not end
+    Mismatch(String),
+}
+
+// Naive interpreter of the raw commands without any optimization, it defines the meaning of a program.
+// `config.max_steps` counts every raw command.
+pub fn run_reference(code: &str, mode: ParseMode, args: Option<&[u8]>, config: &Config) -> Outcome {
+    let program = CommandClassic::code_to_tokens(code.to_string(), mode, config.debug_dump)
+        .and_then(|tokens| Ok((loop_jumps(&tokens)?, tokens)));
+    match program {
+        Ok((jumps, tokens)) => match config.cell {
+            CellWidth::Bits8 => run_tape::<u8>(&tokens, &jumps, args, config),
+            CellWidth::Bits16 => run_tape::<u16>(&tokens, &jumps, args, config),
+            CellWidth::Bits32 => run_tape::<u32>(&tokens, &jumps, args, config),
+            CellWidth::Unbounded => run_tape::<BigInt>(&tokens, &jumps, args, config),
+        },
+        Err(error) => Outcome {
+            output: Vec::new(),
+            result: Err(error),
+            pointer: 0,
+            cells: Vec::new(),
+        },
+    }
+}
+
+// Run the code with the optimized commands of `Command::code_to_tokens`
+pub fn run_optimized(code: &str, mode: ParseMode, args: Option<&[u8]>, config: &Config) -> Outcome {
+    let mut bf = Interpreter::new(config.clone());
+    let mut output = Vec::new();
+    let mut input = args;
+    let result = Command::code_to_tokens(code.to_string(), mode, config).and_then(|tokens| {
+        bf.load(tokens);
+        bf.run(input.as_mut().map(|a| a as &mut dyn Read), &mut output)
+            .map(|_| ())
+    });
+
+    Outcome {
+        output,
+        result,
+        pointer: bf.pointer(),
+        cells: bf.non_zero_cells(),
+    }
+}
+
+// Compare the optimized interpreter with the reference interpreter: same output, same error kind and memory cell
+// and, when both end, same memory and pointer. Error positions may differ, merged commands report the first one.
+pub fn differential_test(
+    code: &str,
+    mode: ParseMode,
+    args: Option<&[u8]>,
+    config: &Config,
+) -> Verdict {
+    let reference = run_reference(code, mode, args, config);
+    if let Err(InterpreterError::StepLimitReached(..)) = reference.result {
+        return Verdict::Inconclusive;
+    }
+    let optimized = run_optimized(code, mode, args, config);
+
+    let same_result = match (&reference.result, &optimized.result) {
+        (Ok(()), Ok(())) => true,
+        (
+            Err(InterpreterError::CellOverflow(_, expected)),
+            Err(InterpreterError::CellOverflow(_, found)),
+        )
+        | (
+            Err(InterpreterError::PointerOutOfBounds(_, expected)),
+            Err(InterpreterError::PointerOutOfBounds(_, found)),
+        ) => expected == found,
+        (Err(expected), Err(found)) => discriminant(expected) == discriminant(found),
+        _ => false,
+    };
+    if !same_result {
+        return Verdict::Mismatch(format!(
+            "result: expected {:?}, found {:?}",
+            reference.result, optimized.result
+        ));
+    }
+    if reference.output != optimized.output {
+        return Verdict::Mismatch(format!(
+            "output: expected {:?}, found {:?}",
+            reference.output, optimized.output
+        ));
+    }
+    if reference.result.is_ok()
+        && (reference.pointer != optimized.pointer || reference.cells != optimized.cells)
+    {
+        return Verdict::Mismatch(format!(
+            "memory: expected pointer {} and cells {:?}, found pointer {} and cells {:?}",
+            reference.pointer, reference.cells, optimized.pointer, optimized.cells
+        ));
+    }
+
+    Verdict::Match
+}
+
+// Index of the matching bracket of every loop command
+fn loop_jumps(tokens: &[CommandClassic]) -> Result<Vec<usize>, InterpreterError> {
+    let mut jumps = vec![0usize; tokens.len()];
+    let mut open_loop: Vec<usize> = Vec::new();
+
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            CommandClassic::OpenLoop(_) => open_loop.push(index),
+            CommandClassic::ClosedLoop(i) => match open_loop.pop() {
+                Some(open_index) => {
+                    jumps[open_index] = index;
+                    jumps[index] = open_index;
+                }
+                None => return Err(InterpreterError::MalformedClosedLoop(*i)),
+            },
+            _ => continue,
+        }
+    }
+
+    match open_loop.first() {
+        Some(open_index) => Err(InterpreterError::MalformedOpenLoop(
+            tokens[*open_index].index_file(),
+        )),
+        None => Ok(jumps),
+    }
+}
+
+fn run_tape<C: Cell>(
+    tokens: &[CommandClassic],
+    jumps: &[usize],
+    args: Option<&[u8]>,
+    config: &Config,
+) -> Outcome {
+    let mut tape: Tape<C> = Tape::new(config.tape_size, config.tape);
+    let mut output = Vec::new();
+    let result = execute(tokens, jumps, args, config, &mut tape, &mut output);
+
+    Outcome {
+        output,
+        result,
+        pointer: tape.position(),
+        cells: tape.non_zero_cells(),
+    }
+}
+
+fn execute<C: Cell>(
+    tokens: &[CommandClassic],
+    jumps: &[usize],
+    mut input: Option<&[u8]>,
+    config: &Config,
+    tape: &mut Tape<C>,
+    output: &mut Data,
+) -> Result<(), InterpreterError> {
+    let mut token_index = 0usize;
+    let mut steps = 0u64;
+
+    while let Some(token) = tokens.get(token_index) {
+        if config.max_steps.is_some_and(|max_steps| steps >= max_steps) {
+            return Err(InterpreterError::StepLimitReached(
+                steps,
+                token.index_file(),
+            ));
+        }
+        steps += 1;
+
+        match token {
+            CommandClassic::Increase(i) => {
+                tape.set(config.overflow.add(tape.get(), 1, *i, tape.position())?)
+            }
+            CommandClassic::Decrease(i) => {
+                tape.set(config.overflow.add(tape.get(), -1, *i, tape.position())?)
+            }
+            CommandClassic::Left(i) => move_pointer(tape, -1, *i)?,
+            CommandClassic::Right(i) => move_pointer(tape, 1, *i)?,
+            CommandClassic::Input(_) => {
+                let value = match input.as_mut() {
+                    Some(reader) => Interpreter::read_byte(reader)?,
+                    None => None,
+                };
+                match (value, config.eof) {
+                    (Some(value), _) => tape.set(C::from_byte(value)),
+                    (None, EofPolicy::Unchanged) => {}
+                    (None, EofPolicy::Zero) => tape.set(C::default()),
+                    (None, EofPolicy::MaxValue) => tape.set(C::max_value()),
+                    (None, EofPolicy::ZeroThenError) if input.is_some() => {
+                        tape.set(C::default());
+                        input = None;
+                    }
+                    (None, EofPolicy::ZeroThenError | EofPolicy::Error) => {
+                        return Err(InterpreterError::MissingArgs)
+                    }
+                }
+            }
+            CommandClassic::Output(_) => output.push(tape.get().to_byte()),
+            // Only the output of the program is compared
+            CommandClassic::Dump(_) => {}
+            CommandClassic::OpenLoop(_) => {
+                if tape.get().is_zero() {
+                    token_index = jumps[token_index];
+                }
+            }
+            CommandClassic::ClosedLoop(_) => {
+                if !tape.get().is_zero() {
+                    token_index = jumps[token_index];
+                }
+            }
+        }
+
+        token_index += 1;
+    }
+
+    Ok(())
+}
+
+fn move_pointer<C: Cell>(
+    tape: &mut Tape<C>,
+    offset: isize,
+    index_file: usize,
+) -> Result<(), InterpreterError> {
+    tape.move_pointer(offset)
+        .ok_or(InterpreterError::PointerOutOfBounds(
+            index_file,
+            tape.position(),
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brainfuck_interpreter::{
+        builder::InterpreterBuilder, config::OverflowPolicy, optimizer::Pass, tape::TapePolicy,
+    };
+
+    fn checked() -> Config {
+        InterpreterBuilder::new()
+            .overflow(OverflowPolicy::Error)
+            .tape(TapePolicy::Error)
+            .max_steps(10_000)
+            .config()
+            .clone()
+    }
+
+    fn bignum() -> Config {
+        InterpreterBuilder::new()
+            .cell(CellWidth::Unbounded)
+            .max_steps(10_000)
+            .config()
+            .clone()
+    }
+
+    fn grow_both() -> Config {
+        InterpreterBuilder::new()
+            .tape(TapePolicy::GrowBoth)
+            .tape_size(1)
+            .max_steps(10_000)
+            .config()
+            .clone()
+    }
+
+    fn assert_match(code: &str, config: &Config) {
+        match differential_test(code, ParseMode::Strict, None, config) {
+            Verdict::Match => {}
+            verdict => panic!("{:?}: {:?}", code, verdict),
+        }
+    }
+
+    #[test]
+    fn a_tape_without_cells_has_one() {
+        let config = InterpreterBuilder::new()
+            .tape_size(0)
+            .max_steps(10_000)
+            .config()
+            .clone();
+        assert_match("++>+<.[-]>+.", &config);
+    }
+
+    #[test]
+    fn moves_leaving_a_checked_tape_fail_even_if_they_come_back() {
+        for code in ["<>+.", ">>>>><<<<<+.", "+[<>-]", ".<>.", "+[->>>><<<<]"] {
+            let config = InterpreterBuilder::new()
+                .tape(TapePolicy::Error)
+                .tape_size(3)
+                .overflow(OverflowPolicy::Error)
+                .config()
+                .clone();
+            let outcome = run_optimized(code, ParseMode::Strict, None, &config);
+            assert!(
+                matches!(
+                    outcome.result,
+                    Err(InterpreterError::PointerOutOfBounds(..))
+                ),
+                "{:?}: {:?}",
+                code,
+                outcome.result
+            );
+            assert_match(code, &config);
+        }
+        assert_match("<>+.", &checked());
+    }
+
+    #[test]
+    fn negative_bignum_cells_never_end_fused_loops() {
+        // `[->+<]` is a multiply and `[-->+<]` an add until zero
+        for code in ["-[->+<]", "-[->++>+++<<]", "+[-->+<]", "-[-->+<]"] {
+            let outcome = run_optimized(code, ParseMode::Strict, None, &bignum());
+            assert!(
+                matches!(outcome.result, Err(InterpreterError::InfinityLoopFound(..))),
+                "{:?}: {:?}",
+                code,
+                outcome.result
+            );
+        }
+        assert_match("++++[-->+<]", &bignum());
+        assert_match("+++[->++>+++<<]", &bignum());
+    }
+
+    #[test]
+    fn fused_loops_find_their_cells_after_the_tape_grows_left() {
+        let outcome = run_optimized("+++[-<+>>++<]", ParseMode::Strict, None, &grow_both());
+        assert!(outcome.result.is_ok(), "{:?}", outcome.result);
+        assert_eq!(outcome.pointer, 0);
+        assert_eq!(
+            outcome.cells,
+            vec![(-1, "3".to_string()), (1, "6".to_string())]
+        );
+        for code in ["+++[-<+>>++<]", "++[->>+<<<<+>>]", "+[-<<<+>>>]>+[-<<+>>]"] {
+            assert_match(code, &grow_both());
+        }
+    }
+
+    #[test]
+    fn checked_fused_loops_do_not_iterate() {
+        // The counter of the last loop is about 2^60, it would take longer than any deadline to iterate
+        let code = format!("+{}[-->+<]", "[->++<]>".repeat(60));
+        let mut config = bignum();
+        config.overflow = OverflowPolicy::Error;
+        let outcome = run_optimized(&code, ParseMode::Strict, None, &config);
+        assert!(outcome.result.is_ok(), "{:?}", outcome.result);
+
+        // Around 2^32 iterations until the counter overflows, the cell is itself in a tape of one cell
+        let config = InterpreterBuilder::new()
+            .cell(CellWidth::Bits32)
+            .overflow(OverflowPolicy::Error)
+            .tape_size(1)
+            .config()
+            .clone();
+        let outcome = run_optimized("+[->++<]", ParseMode::Strict, None, &config);
+        assert!(
+            matches!(outcome.result, Err(InterpreterError::CellOverflow(_, 0))),
+            "{:?}",
+            outcome.result
+        );
+        // The counter never changes and no cell overflows
+        let outcome = run_optimized("+[->+<]", ParseMode::Strict, None, &config);
+        assert!(
+            matches!(outcome.result, Err(InterpreterError::InfinityLoopFound(..))),
+            "{:?}",
+            outcome.result
+        );
+        for code in [
+            "+++++[-->+<]",
+            "++++++++[-->+<]",
+            "+++[-->+++<]",
+            "+[->+<]>[-<++>]",
+        ] {
+            assert_match(code, &checked());
+        }
+    }
+
+    #[test]
+    fn checked_multiply_loops_report_the_first_cell_to_overflow() {
+        // Both cells start at 250, the cell 2 overflows in the 2nd iteration and the cell 1 in the 6th
+        let code = "++++++++++[->+++++++++++++++++++++++++>+++++++++++++++++++++++++<<]++++++++++[->+>+++<<]";
+        let config = InterpreterBuilder::new()
+            .overflow(OverflowPolicy::Error)
+            .config()
+            .clone();
+        for outcome in [
+            run_reference(code, ParseMode::Strict, None, &config),
+            run_optimized(code, ParseMode::Strict, None, &config),
+        ] {
+            assert!(
+                matches!(outcome.result, Err(InterpreterError::CellOverflow(_, 2))),
+                "{:?}",
+                outcome.result
+            );
+        }
+    }
+
+    #[test]
+    fn loops_never_entered_do_not_fail_when_folded() {
+        // `[]` would never end, but its loop is skipped
+        let config = InterpreterBuilder::new()
+            .pass(Pass::DeadLoops, false)
+            .max_steps(10_000)
+            .config()
+            .clone();
+        assert_match("[-][[+].+[]-]+.", &config);
+    }
+}
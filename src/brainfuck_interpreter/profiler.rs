@@ -0,0 +1,221 @@
+use super::{
+    command::{BufferOptions, Command, LoopOptions},
+    source::{Position, SourceMap},
+};
+use std::{cmp::Reverse, fmt, time::Duration};
+
+// Commands shown in the report, the most expensive first
+const REPORT_COMMANDS: usize = 10;
+
+// Executions and time of every command of the loaded program
+#[derive(Clone, Debug, Default)]
+pub struct Profiler {
+    executions: Vec<u64>, // by index of the command
+    time: Vec<Duration>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(super) fn record(&mut self, index: usize, elapsed: Duration) {
+        if index >= self.executions.len() {
+            self.executions.resize(index + 1, 0);
+            self.time.resize(index + 1, Duration::ZERO);
+        }
+        self.executions[index] += 1;
+        self.time[index] += elapsed;
+    }
+
+    // Results of the commands in `tokens`, the positions are found in `source`
+    pub fn report(&self, tokens: &[Command], source: &SourceMap) -> Profile {
+        let executions = |index: usize| self.executions.get(index).copied().unwrap_or(0);
+        let time = |index: usize| self.time.get(index).copied().unwrap_or_default();
+        let total: Duration = self.time.iter().sum();
+
+        let commands = tokens
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| executions(*index) > 0)
+            .map(|(index, command)| CommandProfile {
+                index,
+                command: command.clone(),
+                position: source.position(command.index_file()),
+                executions: executions(index),
+                time: time(index),
+            })
+            .collect();
+
+        let loops = tokens
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| executions(*index) > 0)
+            .filter_map(|(index, command)| {
+                let Command::Loop(options, index_file) = command else {
+                    return None;
+                };
+                let (iterations, time, pattern) = match options {
+                    // The body runs once for every `]` executed
+                    LoopOptions::PointerStart(Some(end)) => (
+                        Some(executions(*end)),
+                        (index..=*end).map(time).sum(),
+                        missed_pattern(&tokens[index + 1..*end]),
+                    ),
+                    LoopOptions::PointerEnd(_) => return None,
+                    options => (None, time(index), fused_pattern(options)),
+                };
+                Some(LoopProfile {
+                    index_file: *index_file,
+                    position: source.position(*index_file),
+                    entries: executions(index),
+                    iterations,
+                    time,
+                    pattern,
+                })
+            })
+            .collect();
+
+        Profile {
+            steps: self.executions.iter().sum(),
+            total,
+            commands,
+            loops,
+        }
+    }
+}
+
+// Why `loop_token` kept a loop with a body, the fused loops are a single command
+fn missed_pattern(body: &[Command]) -> String {
+    let moves: isize = body
+        .iter()
+        .map(|command| match command {
+            Command::Move(offset, _) => *offset,
+            _ => 0,
+        })
+        .sum();
+    let control: i64 = body
+        .iter()
+        .map(|command| match command {
+            Command::Add(value, 0, _) => *value,
+            _ => 0,
+        })
+        .sum();
+    let has = |check: fn(&Command) -> bool| body.iter().any(check);
+
+    if has(|command| matches!(command, Command::Loop(..))) {
+        "contains loops".to_string()
+    } else if has(|command| matches!(command, Command::Buffer(BufferOptions::Input, ..))) {
+        "reads input".to_string()
+    } else if has(|command| matches!(command, Command::Buffer(BufferOptions::Output, ..))) {
+        "writes output".to_string()
+    } else if has(|command| matches!(command, Command::Dump(_))) {
+        "debug dump".to_string()
+    } else if moves != 0 {
+        format!("pointer moves {}", moves)
+    } else if has(|command| matches!(command, Command::Set(..))) {
+        "sets cells".to_string()
+    } else if control != -1 {
+        format!("current cell changes by {}", control)
+    } else {
+        "adds split by checked arithmetic".to_string()
+    }
+}
+
+fn fused_pattern(options: &LoopOptions) -> String {
+    match options {
+        LoopOptions::Comment => "comment",
+        LoopOptions::AddToReset(_) => "fused: reset",
+        LoopOptions::MoveToCell(_) => "fused: scan",
+        LoopOptions::CutAdd(..) => "fused: add until zero",
+        LoopOptions::MultiplyAdd(_) => "fused: multiply",
+        LoopOptions::PointerStart(_) | LoopOptions::PointerEnd(_) => "unconnected",
+    }
+    .to_string()
+}
+
+#[derive(Clone, Debug)]
+pub struct CommandProfile {
+    pub index: usize,
+    pub command: Command,
+    pub position: Position,
+    pub executions: u64,
+    pub time: Duration,
+}
+
+// A loop of the program, keyed by the position of its `[`
+#[derive(Clone, Debug)]
+pub struct LoopProfile {
+    pub index_file: usize,
+    pub position: Position,
+    pub entries: u64,
+    pub iterations: Option<u64>, // `None` when the loop is a single command
+    pub time: Duration,          // with the loops inside it
+    pub pattern: String,         // why the optimizer kept it, or the command it became
+}
+
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub steps: u64,
+    pub total: Duration,
+    pub commands: Vec<CommandProfile>,
+    pub loops: Vec<LoopProfile>,
+}
+
+impl Profile {
+    fn share(&self, time: Duration) -> f64 {
+        if self.total.is_zero() {
+            0.0
+        } else {
+            100.0 * time.as_secs_f64() / self.total.as_secs_f64()
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} steps in {:?}", self.steps, self.total)?;
+
+        let mut loops: Vec<&LoopProfile> = self.loops.iter().collect();
+        loops.sort_by_key(|profile| Reverse(profile.time));
+        writeln!(
+            f,
+            "\n{:<12}{:>12}{:>14}{:>9}  pattern",
+            "loop", "entries", "iterations", "time"
+        )?;
+        for profile in loops {
+            let iterations = match profile.iterations {
+                Some(iterations) => iterations.to_string(),
+                None => "-".to_string(),
+            };
+            writeln!(
+                f,
+                "{:<12}{:>12}{:>14}{:>8.1}%  {}",
+                profile.position.to_string(),
+                profile.entries,
+                iterations,
+                self.share(profile.time),
+                profile.pattern
+            )?;
+        }
+
+        let mut commands: Vec<&CommandProfile> = self.commands.iter().collect();
+        commands.sort_by_key(|profile| Reverse(profile.time));
+        write!(
+            f,
+            "\n{:<12}{:>12}{:>9}  command",
+            "position", "executions", "time"
+        )?;
+        for profile in commands.into_iter().take(REPORT_COMMANDS) {
+            write!(
+                f,
+                "\n{:<12}{:>12}{:>8.1}%  {:?}",
+                profile.position.to_string(),
+                profile.executions,
+                self.share(profile.time),
+                profile.command
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,433 @@
+use super::{
+    cell::CellWidth,
+    command::{BufferOptions, Command, LoopOptions},
+    config::{Config, EofPolicy, OverflowPolicy},
+    error::InterpreterError,
+    interpreter::DUMP_RADIUS,
+    tape::TapePolicy,
+};
+use std::fmt::Write;
+
+// Same behaviour as `Interpreter`, the errors panic with the messages of `InterpreterError`
+const RUNTIME: &str = r##"
+    #[derive(PartialEq)]
+    enum TapePolicy {
+        Wrap,
+        Error,
+        GrowRight,
+        GrowBoth,
+    }
+
+    #[derive(PartialEq)]
+    enum EofPolicy {
+        Unchanged,
+        Zero,
+        MaxValue,
+        Error,
+        ZeroThenError,
+    }
+
+    struct Tape {
+        cells: Vec<Cell>,
+        pointer: usize,
+        origin: usize,
+        input_ended: bool,
+    }
+
+    impl Tape {
+        fn current(&self) -> Cell {
+            self.cells[self.pointer]
+        }
+
+        // Cell number as seen by the program, negative when the tape has grown to the left
+        fn position(&self) -> isize {
+            self.pointer as isize - self.origin as isize
+        }
+
+        // Cell number of the cell at `offset` from the pointer, the cyclic tape wraps it
+        fn position_at(&self, offset: isize) -> isize {
+            let position = self.position() + offset;
+            match TAPE {
+                TapePolicy::Wrap => position.rem_euclid(self.cells.len() as isize),
+                _ => position,
+            }
+        }
+
+        fn infinite(&self, index_file: usize) -> ! {
+            panic!(
+                "An infinite loop has been found, at code position `{}`, with a current value `{}` in memory cell `{}`",
+                index_file,
+                self.current(),
+                self.position()
+            )
+        }
+
+        // Index of the cell at `offset` from the pointer, following the tape policy
+        fn at(&mut self, offset: isize, index_file: usize) -> usize {
+            let len = self.cells.len() as isize;
+            let target = self.pointer as isize + offset;
+            match TAPE {
+                _ if (0..len).contains(&target) => target as usize,
+                TapePolicy::Wrap => target.rem_euclid(len) as usize,
+                TapePolicy::GrowRight | TapePolicy::GrowBoth if target >= len => {
+                    // Doubling keeps the cost of growing constant on average
+                    let new_len = (target as usize + 1).max(self.cells.len() * 2);
+                    self.cells.resize(new_len, 0);
+                    target as usize
+                }
+                TapePolicy::GrowBoth => {
+                    let missing = target.unsigned_abs().max(self.cells.len());
+                    self.cells.splice(0..0, std::iter::repeat(0).take(missing));
+                    self.pointer += missing;
+                    self.origin += missing;
+                    missing - target.unsigned_abs()
+                }
+                // The pointer leaves from the last cell in the direction of the move
+                _ => panic!(
+                    "The pointer has left the tape at code position `{}`, from memory cell `{}`",
+                    index_file,
+                    if target < 0 { 0 } else { len - 1 } - self.origin as isize
+                ),
+            }
+        }
+
+        fn store(&mut self, cell: usize, offset: isize, value: i64, index_file: usize) {
+            if CHECKED && !(0..=CELL_MAX as i64).contains(&value) {
+                panic!(
+                    "The value of memory cell `{}` has overflowed at code position `{}`",
+                    self.position_at(offset),
+                    index_file
+                );
+            }
+            self.cells[cell] = value as Cell;
+        }
+
+        fn add(&mut self, offset: isize, value: i64, index_file: usize) {
+            let cell = self.at(offset, index_file);
+            let value = if CHECKED {
+                self.cells[cell] as i64 + value
+            } else {
+                self.cells[cell].wrapping_add(value as Cell) as i64
+            };
+            self.store(cell, offset, value, index_file);
+        }
+
+        fn set(&mut self, offset: isize, value: i64, index_file: usize) {
+            let cell = self.at(offset, index_file);
+            self.store(cell, offset, value, index_file);
+        }
+
+        fn shift(&mut self, offset: isize, index_file: usize) {
+            self.pointer = self.at(offset, index_file);
+        }
+
+        fn input(
+            &mut self,
+            input: &mut impl Read,
+            output: &mut impl Write,
+            offset: isize,
+            index_file: usize,
+        ) {
+            let cell = self.at(offset, index_file);
+            // The program may be waiting for the user, show the pending output
+            output.flush().unwrap_or_else(|e| io_error(e));
+            let mut buffer = [0u8; 1];
+            let byte = if self.input_ended {
+                None
+            } else {
+                loop {
+                    match input.read(&mut buffer) {
+                        Ok(0) => break None,
+                        Ok(_) => break Some(buffer[0]),
+                        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                        Err(e) => io_error(e),
+                    }
+                }
+            };
+            match (byte, EOF) {
+                (Some(byte), _) => self.cells[cell] = byte as Cell,
+                (None, EofPolicy::Unchanged) => {}
+                (None, EofPolicy::Zero) => self.cells[cell] = 0,
+                (None, EofPolicy::MaxValue) => self.cells[cell] = Cell::MAX,
+                (None, EofPolicy::ZeroThenError) if !self.input_ended => {
+                    self.cells[cell] = 0;
+                    self.input_ended = true;
+                }
+                (None, EofPolicy::ZeroThenError | EofPolicy::Error) => panic!("Missing arguments"),
+            }
+        }
+
+        fn output(&mut self, output: &mut impl Write, offset: isize, index_file: usize) {
+            let cell = self.at(offset, index_file);
+            output
+                .write_all(&[self.cells[cell] as u8])
+                .unwrap_or_else(|e| io_error(e));
+        }
+
+        // `#`: the cells around the pointer are printed to stderr, the current one in brackets
+        fn dump(&self, index_file: usize) {
+            let first = self.pointer.saturating_sub(DUMP_RADIUS);
+            let last = (self.pointer + DUMP_RADIUS).min(self.cells.len() - 1);
+            let cells: Vec<String> = (first..=last)
+                .map(|cell| {
+                    let position = cell as isize - self.origin as isize;
+                    if cell == self.pointer {
+                        format!("[{}:{}]", position, self.cells[cell])
+                    } else {
+                        format!("{}:{}", position, self.cells[cell])
+                    }
+                })
+                .collect();
+            eprintln!(
+                "# code position `{}`, memory cell `{}`: {}",
+                index_file,
+                self.position(),
+                cells.join(" ")
+            );
+        }
+
+        fn comment(&self, index_file: usize) {
+            if self.current() != 0 {
+                self.infinite(index_file);
+            }
+        }
+
+        fn add_to_reset(&mut self, step: i64, index_file: usize) {
+            if self.current() == 0 {
+                return;
+            }
+            if CHECKED {
+                // Only decreasing by a divisor of the value, otherwise 0 is skipped
+                if !(step < 0 && self.current() as i64 % step == 0) {
+                    panic!(
+                        "The value of memory cell `{}` has overflowed at code position `{}`",
+                        self.position(),
+                        index_file
+                    );
+                }
+            } else if steps_to_zero(self.current(), step as Cell).is_none() {
+                self.infinite(index_file);
+            }
+            self.cells[self.pointer] = 0;
+        }
+
+        fn scan_zero(&mut self, stride: isize, index_file: usize) {
+            if TAPE != TapePolicy::Wrap && stride == 1 {
+                // The next cell is outside the tape, a growing tape adds it with 0
+                match self.cells[self.pointer..].iter().position(|cell| *cell == 0) {
+                    Some(index) => self.pointer += index,
+                    None => {
+                        self.pointer = self.cells.len() - 1;
+                        self.shift(1, index_file);
+                    }
+                }
+                return;
+            }
+            let start = self.pointer;
+            while self.current() != 0 {
+                self.shift(stride, index_file);
+                if TAPE == TapePolicy::Wrap && self.pointer == start {
+                    // Every cell is visited when the stride and the length are coprime
+                    match gcd(stride.unsigned_abs(), self.cells.len()) {
+                        1 => panic!(
+                            "An infinite movement loop has been found, at code position `{}`, memory does not contain value `0`",
+                            index_file
+                        ),
+                        _ => panic!(
+                            "An infinite movement loop has been found, at code position `{}`, the pointer never stops moving, memory cell movement is `{}`",
+                            index_file, stride
+                        ),
+                    }
+                }
+            }
+        }
+
+        // Loop whose body adds `factor` to the cell at `offset` until the current cell is 0
+        fn add_until_zero(&mut self, updates: &[(isize, i64)], index_file: usize) {
+            if self.current() == 0 {
+                return;
+            }
+            let len = self.cells.len() as isize;
+            let aliased = |offset: isize| match TAPE {
+                TapePolicy::Wrap => offset % len == 0,
+                _ => offset == 0,
+            };
+            let step: i64 = updates
+                .iter()
+                .filter(|(offset, _)| aliased(*offset))
+                .map(|(_, factor)| factor)
+                .sum();
+            if CHECKED {
+                // Iterating finds which cell overflows first, bounded cells always overflow or reach 0
+                // unless no cell changes
+                let unchanged = updates
+                    .iter()
+                    .all(|(offset, factor)| aliased(*offset) || *factor == 0);
+                if step == 0 && unchanged {
+                    self.infinite(index_file);
+                }
+                while self.current() != 0 {
+                    for (offset, factor) in updates {
+                        self.add(*offset, *factor, index_file);
+                    }
+                }
+                return;
+            }
+            // The cells are checked before the loop runs, growing the tape could move them
+            for (offset, _) in updates {
+                self.at(*offset, index_file);
+            }
+            // The loop runs `steps` times, the first solution of `value + steps * step = 0`
+            let steps = match steps_to_zero(self.current(), step as Cell) {
+                Some(steps) => steps,
+                None => self.infinite(index_file),
+            };
+            for (offset, factor) in updates.iter().filter(|(offset, _)| !aliased(*offset)) {
+                let cell = self.at(*offset, index_file);
+                self.cells[cell] = self.cells[cell].wrapping_add((*factor as u64).wrapping_mul(steps) as Cell);
+            }
+            self.cells[self.pointer] = 0;
+        }
+    }
+
+    // Times `step` has to be added to `value` to reach 0 modulo 2^bits, `None` if it never does
+    fn steps_to_zero(value: Cell, step: Cell) -> Option<u64> {
+        if value == 0 {
+            return Some(0);
+        }
+        if step == 0 || step.trailing_zeros() > value.trailing_zeros() {
+            return None;
+        }
+        // The odd part of the step has an inverse, Newton's iteration doubles its correct bits each round
+        let shift = step.trailing_zeros();
+        let odd = (step >> shift) as u64;
+        let mut inverse = odd;
+        for _ in 0..5 {
+            inverse = inverse.wrapping_mul(2u64.wrapping_sub(odd.wrapping_mul(inverse)));
+        }
+        Some(((value >> shift) as u64).wrapping_neg().wrapping_mul(inverse) & (CELL_MAX >> shift))
+    }
+
+    fn gcd(a: usize, b: usize) -> usize {
+        match b {
+            0 => a,
+            _ => gcd(b, a % b),
+        }
+    }
+
+    fn io_error(error: std::io::Error) -> ! {
+        panic!("Execution Error: Input/output failure: {}", error)
+    }
+"##;
+
+// Rust source of `fn run(input: &mut impl Read, output: &mut impl Write)`, which runs `tokens` with the tape,
+// cells and EOF policy of `config`. It does not depend on this crate, a build script can write it to a file
+// to `include!`. The step and time limits are not included.
+pub fn emit_rust(tokens: &[Command], config: &Config) -> Result<String, InterpreterError> {
+    let cell_type = match config.cell {
+        CellWidth::Bits8 => "u8",
+        CellWidth::Bits16 => "u16",
+        CellWidth::Bits32 => "u32",
+        // The standard library has no integer without limits
+        CellWidth::Unbounded => {
+            return Err(InterpreterError::InvalidOption(
+                "--cell".to_string(),
+                "bignum".to_string(),
+            ))
+        }
+    };
+    let tape = match config.tape {
+        TapePolicy::Wrap => "Wrap",
+        TapePolicy::Error => "Error",
+        TapePolicy::GrowRight => "GrowRight",
+        TapePolicy::GrowBoth => "GrowBoth",
+    };
+    let eof = match config.eof {
+        EofPolicy::Unchanged => "Unchanged",
+        EofPolicy::Zero => "Zero",
+        EofPolicy::MaxValue => "MaxValue",
+        EofPolicy::Error => "Error",
+        EofPolicy::ZeroThenError => "ZeroThenError",
+    };
+
+    let mut code = String::from("// Generated by brainfuck emit-rust\n");
+    code.push_str("#[allow(clippy::all, dead_code, unreachable_code, unused)]\n");
+    code.push_str(
+        "pub fn run(input: &mut impl std::io::Read, output: &mut impl std::io::Write) {\n",
+    );
+    code.push_str("    use std::io::{ErrorKind, Read, Write};\n\n");
+    writeln!(code, "    type Cell = {};", cell_type).unwrap();
+    writeln!(code, "    const CELL_MAX: u64 = {}::MAX as u64;", cell_type).unwrap();
+    writeln!(
+        code,
+        "    const TAPE_SIZE: usize = {};",
+        config.tape_size.max(1)
+    )
+    .unwrap();
+    writeln!(code, "    const TAPE: TapePolicy = TapePolicy::{};", tape).unwrap();
+    writeln!(code, "    const EOF: EofPolicy = EofPolicy::{};", eof).unwrap();
+    let checked = config.overflow == OverflowPolicy::Error;
+    writeln!(code, "    const CHECKED: bool = {};", checked).unwrap();
+    writeln!(code, "    const DUMP_RADIUS: usize = {};", DUMP_RADIUS).unwrap();
+    code.push_str(RUNTIME);
+    code.push_str("\n    let mut tape = Tape {\n        cells: vec![0; TAPE_SIZE],\n        pointer: 0,\n        origin: 0,\n        input_ended: false,\n    };\n");
+
+    let mut depth = 1usize;
+    for token in tokens {
+        if let Command::Loop(LoopOptions::PointerEnd(_), _) = token {
+            depth -= 1;
+        }
+        let line = match token {
+            Command::Add(value, offset, i) => format!("tape.add({}, {}, {});", offset, value, i),
+            Command::Set(value, offset, i) => format!("tape.set({}, {}, {});", offset, value, i),
+            Command::Move(offset, i) => format!("tape.shift({}, {});", offset, i),
+            Command::Buffer(BufferOptions::Input, offset, i) => {
+                format!("tape.input(input, output, {}, {});", offset, i)
+            }
+            Command::Buffer(BufferOptions::Output, offset, i) => {
+                format!("tape.output(output, {}, {});", offset, i)
+            }
+            Command::Dump(i) => format!("tape.dump({});", i),
+            Command::Loop(LoopOptions::Comment, i) => format!("tape.comment({});", i),
+            Command::Loop(LoopOptions::AddToReset(step), i) => {
+                format!("tape.add_to_reset({}, {});", step, i)
+            }
+            Command::Loop(LoopOptions::MoveToCell(stride), i) => {
+                format!("tape.scan_zero({}, {});", stride, i)
+            }
+            Command::Loop(LoopOptions::CutAdd(offset, value_1, value_2), i) => {
+                add_until_zero(&[(0, *value_1), (*offset, *value_2)], *i)
+            }
+            Command::Loop(LoopOptions::MultiplyAdd(updates), i) => {
+                add_until_zero(&[&[(0, -1)], updates.as_slice()].concat(), *i)
+            }
+            Command::Loop(LoopOptions::PointerStart(Some(_)), _) => {
+                "while tape.current() != 0 {".to_string()
+            }
+            Command::Loop(LoopOptions::PointerEnd(Some(_)), _) => "}".to_string(),
+            Command::Loop(LoopOptions::PointerStart(None) | LoopOptions::PointerEnd(None), _) => {
+                return Err(InterpreterError::UnconnectedLoops)
+            }
+        };
+        writeln!(code, "{:width$}{}", "", line, width = depth * 4).unwrap();
+        if let Command::Loop(LoopOptions::PointerStart(_), _) = token {
+            depth += 1;
+        }
+    }
+
+    code.push_str("    output.flush().unwrap_or_else(|e| io_error(e));\n}\n");
+    Ok(code)
+}
+
+// Call of the runtime for a loop that only adds, the current cell is the first update
+fn add_until_zero(updates: &[(isize, i64)], index_file: usize) -> String {
+    let updates: Vec<String> = updates
+        .iter()
+        .map(|(offset, factor)| format!("({}, {})", offset, factor))
+        .collect();
+    format!(
+        "tape.add_until_zero(&[{}], {});",
+        updates.join(", "),
+        index_file
+    )
+}
@@ -0,0 +1,203 @@
+use super::error::InterpreterError;
+use std::{fmt, str::FromStr};
+
+// Line and column of a character of the source code, both from 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize, // in characters, a tab is one column
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+// `line:column`
+impl FromStr for Position {
+    type Err = InterpreterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InterpreterError::InvalidOption("position".to_string(), s.to_string());
+        let (line, column) = s.split_once(':').ok_or_else(invalid)?;
+        match (line.parse(), column.parse()) {
+            (Ok(line), Ok(column)) if line > 0 && column > 0 => Ok(Self { line, column }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// Source code of a program, the `index_file` of the commands and errors (a character from 1) is found in its lines
+#[derive(Clone, Debug)]
+pub struct SourceMap {
+    name: String,       // file name, `<stdin>` or `<inline>`
+    lines: Vec<String>, // without the line break
+    starts: Vec<usize>, // index_file of the first character of every line
+}
+
+impl SourceMap {
+    pub fn new(name: &str, code: &str) -> Self {
+        let lines: Vec<String> = code
+            .split('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line).to_string())
+            .collect();
+        let mut starts = Vec::with_capacity(lines.len());
+        let mut start = 1;
+        for line in code.split('\n') {
+            starts.push(start);
+            start += line.chars().count() + 1;
+        }
+        Self {
+            name: name.to_string(),
+            lines,
+            starts,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn position(&self, index_file: usize) -> Position {
+        let line = self
+            .starts
+            .partition_point(|start| *start <= index_file)
+            .max(1);
+        Position {
+            line,
+            column: (index_file + 1)
+                .saturating_sub(self.starts[line - 1])
+                .max(1),
+        }
+    }
+
+    // `None` when the position is outside the source code
+    pub fn index_file(&self, position: Position) -> Option<usize> {
+        let line = position.line.checked_sub(1)?;
+        let column = position.column.checked_sub(1)?;
+        (column < self.lines.get(line)?.chars().count()).then(|| self.starts[line] + column)
+    }
+
+    // `name:line:column`
+    pub fn location(&self, index_file: usize) -> String {
+        format!("{}:{}", self.name, self.position(index_file))
+    }
+
+    // The line of `index_file` with a caret under its character:
+    //  --> program.bf:2:3
+    //   |
+    // 2 | +]
+    //   |  ^
+    pub fn snippet(&self, index_file: usize) -> String {
+        let position = self.position(index_file);
+        let line = self.lines.get(position.line - 1).map_or("", String::as_str);
+        let number = position.line.to_string();
+        let margin = " ".repeat(number.len());
+        // Tabs are kept so the caret is under the character in any terminal
+        let indent: String = line
+            .chars()
+            .take(position.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        format!(
+            "{margin}--> {}\n{margin} |\n{number} | {line}\n{margin} | {indent}^",
+            self.location(index_file)
+        )
+    }
+
+    // The error with the location and the snippet of its position, errors without a position or already located
+    // are returned unchanged
+    pub fn locate(&self, error: InterpreterError) -> InterpreterError {
+        match error.index_file() {
+            Some(_) if matches!(error, InterpreterError::InSource(..)) => error,
+            Some(index_file) => InterpreterError::InSource(
+                Box::new(error),
+                self.location(index_file),
+                self.snippet(index_file),
+            ),
+            None => error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brainfuck_interpreter::{
+        builder::InterpreterBuilder, command::ParseMode, optimizer::OptLevel,
+    };
+
+    #[test]
+    fn lines_end_with_either_line_break() {
+        // `+` is 1, `\r` 2, `\n` 3, `-` 4, `\n` 5 and `>` 6
+        let source = SourceMap::new("program.bf", "+\r\n-\n>");
+        assert_eq!(source.position(1), Position { line: 1, column: 1 });
+        assert_eq!(source.position(4), Position { line: 2, column: 1 });
+        assert_eq!(source.position(6), Position { line: 3, column: 1 });
+        assert_eq!(source.index_file(Position { line: 2, column: 1 }), Some(4));
+        // The `\r` is not a column
+        assert_eq!(source.index_file(Position { line: 1, column: 2 }), None);
+        assert_eq!(source.index_file(Position { line: 4, column: 1 }), None);
+    }
+
+    #[test]
+    fn columns_count_characters() {
+        // A leading loop is a comment even in strict mode, `→` is 6
+        let code = "[ñ\n+]→";
+        let source = SourceMap::new("<stdin>", code);
+        assert_eq!(source.position(2), Position { line: 1, column: 2 });
+        assert_eq!(source.position(6), Position { line: 2, column: 3 });
+        assert_eq!(source.location(6), "<stdin>:2:3");
+
+        let error = InterpreterBuilder::new()
+            .parse_mode(ParseMode::Strict)
+            .source(source)
+            .compile(code)
+            .unwrap_err();
+        assert_eq!(error.index_file(), Some(6));
+        assert_eq!(
+            error.to_string().lines().next(),
+            Some("Unknown character instruction `→` at `<stdin>:2:3`")
+        );
+    }
+
+    #[test]
+    fn the_caret_is_under_the_character() {
+        let source = SourceMap::new("<inline>", "+\n\t+]");
+        assert_eq!(
+            source.snippet(5),
+            " --> <inline>:2:3\n  |\n2 | \t+]\n  | \t ^"
+        );
+        assert_eq!(
+            InterpreterError::MalformedClosedLoop(5).to_string(),
+            "Closed loop does not match an open loop at index: `5`"
+        );
+        let error = source.locate(InterpreterError::MalformedClosedLoop(5));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Closed loop does not match an open loop at `<inline>:2:3`\n{}",
+                source.snippet(5)
+            )
+        );
+        // A located error is not located again
+        assert_eq!(source.locate(error).to_string().matches("-->").count(), 1);
+    }
+
+    #[test]
+    fn the_interpreter_knows_the_position_of_the_next_command() {
+        let builder = InterpreterBuilder::new()
+            .opt_level(OptLevel::O0)
+            .source(SourceMap::new("<inline>", "+\n+>"));
+        let tokens = builder.compile("+\n+>").unwrap();
+        let mut bf = builder.build();
+        bf.load(tokens);
+        let mut output = Vec::new();
+        for (line, column) in [(1, 1), (2, 1), (2, 2)] {
+            assert_eq!(bf.position(), Some(Position { line, column }));
+            bf.run_steps(1, None, &mut output).unwrap();
+        }
+        assert_eq!(bf.position(), None);
+    }
+}
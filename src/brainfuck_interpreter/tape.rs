@@ -0,0 +1,240 @@
+use std::str::FromStr;
+
+use super::{cell::Cell, error::InterpreterError};
+
+// What happens when the pointer goes past one end of the tape
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TapePolicy {
+    #[default]
+    Wrap, // the tape is traversed cyclically
+    Error,     // leaving the tape is an error
+    GrowRight, // the tape grows to the right, leaving it by the left is an error
+    GrowBoth,  // the tape grows in both directions
+}
+
+impl FromStr for TapePolicy {
+    type Err = InterpreterError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "wrap" => Ok(Self::Wrap),
+            "error" => Ok(Self::Error),
+            "grow-right" => Ok(Self::GrowRight),
+            "grow-both" => Ok(Self::GrowBoth),
+            _ => Err(InterpreterError::InvalidOption(
+                "--tape".to_string(),
+                name.to_string(),
+            )),
+        }
+    }
+}
+
+// Why the search of a cell with 0 failed
+pub enum ScanFailure {
+    OutOfBounds, // the pointer would leave the tape, it stays in the last cell visited
+    MemoryFull,  // no cell of the cyclic tape is 0
+    Cycle,       // the cells visited in the cyclic tape are not 0, they repeat forever
+}
+
+// Memory of the interpreter
+pub struct Tape<C: Cell> {
+    cells: Vec<C>,
+    pointer: usize,
+    origin: usize, // cells added to the left, the cell `0` of the program is at `cells[origin]`
+    policy: TapePolicy,
+}
+
+impl<C: Cell> Tape<C> {
+    pub fn new(size: usize, policy: TapePolicy) -> Self {
+        Self {
+            // A tape always has at least one cell
+            cells: vec![C::default(); size.max(1)],
+            pointer: 0,
+            origin: 0,
+            policy,
+        }
+    }
+
+    pub fn get(&self) -> &C {
+        &self.cells[self.pointer]
+    }
+
+    pub fn set(&mut self, value: C) {
+        self.cells[self.pointer] = value;
+    }
+
+    pub fn get_at(&self, index: usize) -> &C {
+        &self.cells[index]
+    }
+
+    pub fn set_at(&mut self, index: usize, value: C) {
+        self.cells[index] = value;
+    }
+
+    pub fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    // Cell number as seen by the program, negative when the tape has grown to the left
+    pub fn position(&self) -> isize {
+        self.pointer as isize - self.origin as isize
+    }
+
+    // Position and value of every cell different from 0
+    pub fn non_zero_cells(&self) -> Vec<(isize, String)> {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| !cell.is_zero())
+            .map(|(index, cell)| (index as isize - self.origin as isize, cell.to_string()))
+            .collect()
+    }
+
+    // Cell at `position` as seen by the program, `None` if it is outside the tape
+    pub fn cell(&self, position: isize) -> Option<&C> {
+        let index = (self.origin as isize).checked_add(position)?;
+        self.cells.get(usize::try_from(index).ok()?)
+    }
+
+    // Position of the cell at `offset` from the pointer without growing the tape, the cyclic tape wraps it
+    pub fn position_at(&self, offset: isize) -> isize {
+        let position = self.position() + offset;
+        match self.policy {
+            TapePolicy::Wrap => position.rem_euclid(self.cells.len() as isize),
+            _ => position,
+        }
+    }
+
+    // Position of the last cell in the direction of `offset`, where a pointer leaving the tape that way exits
+    pub fn boundary(&self, offset: isize) -> isize {
+        let index = if offset < 0 { 0 } else { self.cells.len() - 1 };
+        index as isize - self.origin as isize
+    }
+
+    // Position and value of the cells at most `radius` cells away from the pointer
+    pub fn window(&self, radius: usize) -> Vec<(isize, String)> {
+        let start = self.pointer.saturating_sub(radius);
+        let end = self
+            .pointer
+            .saturating_add(radius)
+            .min(self.cells.len() - 1);
+        (start..=end)
+            .map(|index| {
+                (
+                    index as isize - self.origin as isize,
+                    self.cells[index].to_string(),
+                )
+            })
+            .collect()
+    }
+
+    // Index in `cells` of the cell at `offset` from the pointer, `None` if it is outside the tape
+    pub fn index_at(&mut self, offset: isize) -> Option<usize> {
+        let len = self.cells.len() as isize;
+        let target = (self.pointer as isize).checked_add(offset)?;
+        match self.policy {
+            _ if (0..len).contains(&target) => Some(target as usize),
+            TapePolicy::Wrap => Some(target.rem_euclid(len) as usize),
+            TapePolicy::Error => None,
+            TapePolicy::GrowRight | TapePolicy::GrowBoth if target >= len => {
+                // Doubling keeps the cost of growing constant on average
+                let new_len = (target as usize + 1).max(self.cells.len() * 2);
+                self.cells.resize(new_len, C::default());
+                Some(target as usize)
+            }
+            TapePolicy::GrowRight => None,
+            TapePolicy::GrowBoth => {
+                let missing = target.unsigned_abs().max(self.cells.len());
+                self.cells
+                    .splice(0..0, std::iter::repeat_n(C::default(), missing));
+                self.pointer += missing;
+                self.origin += missing;
+                Some(missing - target.unsigned_abs())
+            }
+        }
+    }
+
+    // Move the pointer `stride` cells at a time until a cell with 0, without moving it one by one
+    pub fn scan_zero(&mut self, stride: isize) -> Result<(), ScanFailure> {
+        let (start, len, step) = (self.pointer, self.cells.len(), stride.unsigned_abs());
+        let found = match (self.policy, stride) {
+            (TapePolicy::Wrap, 1) => C::find_zero(&self.cells[start..])
+                .map(|index| start + index)
+                .or_else(|| C::find_zero(&self.cells[..start])),
+            (TapePolicy::Wrap, -1) => C::rfind_zero(&self.cells[..=start])
+                .or_else(|| C::rfind_zero(&self.cells[start + 1..]).map(|index| start + 1 + index)),
+            // The cells visited repeat after going back to the start
+            (TapePolicy::Wrap, _) => {
+                let mut index = start;
+                loop {
+                    if self.cells[index].is_zero() {
+                        break Some(index);
+                    }
+                    index = (index as isize + stride).rem_euclid(len as isize) as usize;
+                    if index == start {
+                        break None;
+                    }
+                }
+            }
+            (_, 1) => C::find_zero(&self.cells[start..]).map(|index| start + index),
+            (_, -1) => C::rfind_zero(&self.cells[..=start]),
+            _ if stride > 0 => (start..len)
+                .step_by(step)
+                .find(|index| self.cells[*index].is_zero()),
+            _ => (0..=start)
+                .rev()
+                .step_by(step)
+                .find(|index| self.cells[*index].is_zero()),
+        };
+
+        match found {
+            Some(index) => {
+                self.pointer = index;
+                Ok(())
+            }
+            // Every cell is visited when the stride and the length are coprime
+            None if self.policy == TapePolicy::Wrap => match gcd(step, len) {
+                1 => Err(ScanFailure::MemoryFull),
+                _ => Err(ScanFailure::Cycle),
+            },
+            // The next cell is outside the tape, a growing tape adds it with 0
+            None => {
+                self.pointer = if stride > 0 {
+                    start + (len - 1 - start) / step * step
+                } else {
+                    start % step
+                };
+                self.move_pointer(stride).ok_or(ScanFailure::OutOfBounds)
+            }
+        }
+    }
+
+    // Raw access for the JIT, the cells must not grow while the machine code uses them
+    #[cfg(all(feature = "jit", target_os = "linux", target_arch = "x86_64"))]
+    pub(super) fn as_mut_ptr(&mut self) -> *mut C {
+        self.cells.as_mut_ptr()
+    }
+
+    #[cfg(all(feature = "jit", target_os = "linux", target_arch = "x86_64"))]
+    pub(super) fn size(&self) -> usize {
+        self.cells.len()
+    }
+
+    #[cfg(all(feature = "jit", target_os = "linux", target_arch = "x86_64"))]
+    pub(super) fn set_pointer(&mut self, pointer: usize) {
+        self.pointer = pointer;
+    }
+
+    // Move the pointer, `None` if it would leave the tape
+    pub fn move_pointer(&mut self, offset: isize) -> Option<()> {
+        self.pointer = self.index_at(offset)?;
+        Some(())
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
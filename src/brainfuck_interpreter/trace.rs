@@ -0,0 +1,345 @@
+use super::{
+    cell::Cell,
+    command::{BufferOptions, Command, LoopOptions},
+    error::InterpreterError,
+    source::{Position, SourceMap},
+    tape::Tape,
+};
+use num_bigint::BigInt;
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+};
+
+// Start of every trace file, followed by the version of the format and the name of the source code
+const MAGIC: &[u8; 7] = b"BFTRACE";
+const VERSION: u8 = 2;
+
+// A command executed by the interpreter
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceStep {
+    pub token: usize,       // index of the command in the optimized instructions
+    pub index_file: usize,  // source position of the command
+    pub position: Position, // line and column of `index_file`
+    pub pointer: isize,     // memory cell of the pointer after the command
+    pub changes: Vec<(isize, BigInt)>, // memory cells the command can write and their values after it
+}
+
+// Writes a step for every command executed, as variable-length integers: token, index_file, line, column,
+// pointer, number of changes and each memory cell with its value (length and signed bytes)
+pub struct Tracer {
+    writer: Box<dyn Write>,
+    source: SourceMap, // source code of the traced program
+}
+
+impl Tracer {
+    pub fn new(mut writer: Box<dyn Write>, source: &SourceMap) -> Result<Self, InterpreterError> {
+        let mut header = MAGIC.to_vec();
+        header.push(VERSION);
+        write_unsigned(&mut header, source.name().len() as u64);
+        header.extend_from_slice(source.name().as_bytes());
+        writer
+            .write_all(&header)
+            .map_err(InterpreterError::IoError)?;
+        Ok(Self {
+            writer,
+            source: source.clone(),
+        })
+    }
+
+    // Memory cells the command can write, taken before running it: the pointer can move and the tape grow
+    pub(super) fn written<C: Cell>(token: &Command, tape: &Tape<C>) -> Vec<isize> {
+        let offsets = match token {
+            Command::Add(_, offset, _)
+            | Command::Set(_, offset, _)
+            | Command::Buffer(BufferOptions::Input, offset, _) => vec![*offset],
+            Command::Loop(LoopOptions::Comment | LoopOptions::AddToReset(_), _) => vec![0],
+            Command::Loop(LoopOptions::CutAdd(offset, _, _), _) => vec![0, *offset],
+            Command::Loop(LoopOptions::MultiplyAdd(updates), _) => [0]
+                .into_iter()
+                .chain(updates.iter().map(|(offset, _)| *offset))
+                .collect(),
+            Command::Buffer(BufferOptions::Output, ..)
+            | Command::Move(..)
+            | Command::Dump(_)
+            | Command::Loop(_, _) => Vec::new(),
+        };
+        offsets
+            .into_iter()
+            .map(|offset| tape.position_at(offset))
+            .collect()
+    }
+
+    pub(super) fn record<C: Cell>(
+        &mut self,
+        index: usize,
+        token: &Command,
+        tape: &Tape<C>,
+        written: &[isize],
+    ) -> Result<(), InterpreterError> {
+        let mut bytes = Vec::with_capacity(16);
+        let position = self.source.position(token.index_file());
+        write_unsigned(&mut bytes, index as u64);
+        write_unsigned(&mut bytes, token.index_file() as u64);
+        write_unsigned(&mut bytes, position.line as u64);
+        write_unsigned(&mut bytes, position.column as u64);
+        write_signed(&mut bytes, tape.position() as i64);
+        write_unsigned(&mut bytes, written.len() as u64);
+        for position in written {
+            let value = tape
+                .cell(*position)
+                .map(Cell::to_bigint)
+                .unwrap_or_default()
+                .to_signed_bytes_le();
+            write_signed(&mut bytes, *position as i64);
+            write_unsigned(&mut bytes, value.len() as u64);
+            bytes.extend_from_slice(&value);
+        }
+        self.writer
+            .write_all(&bytes)
+            .map_err(InterpreterError::IoError)
+    }
+
+    pub(super) fn flush(&mut self) -> Result<(), InterpreterError> {
+        self.writer.flush().map_err(InterpreterError::IoError)
+    }
+}
+
+// Steps of a recorded run
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trace {
+    pub source: String, // name of the source code
+    pub steps: Vec<TraceStep>,
+}
+
+impl Trace {
+    pub fn read(reader: &mut dyn Read) -> Result<Self, InterpreterError> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(InterpreterError::IoError)?;
+        let mut data = match data.strip_prefix(MAGIC.as_slice()) {
+            Some([VERSION, rest @ ..]) => rest,
+            Some(_) => {
+                return Err(InterpreterError::InvalidTrace(
+                    "unknown version".to_string(),
+                ))
+            }
+            None => return Err(InterpreterError::InvalidTrace("missing header".to_string())),
+        };
+        let len = read_unsigned(&mut data)? as usize;
+        let name = data
+            .get(..len)
+            .ok_or_else(|| InterpreterError::InvalidTrace("missing header".to_string()))?;
+        let source = String::from_utf8_lossy(name).into_owned();
+        data = &data[len..];
+
+        let mut steps = Vec::new();
+        while !data.is_empty() {
+            let token = read_unsigned(&mut data)? as usize;
+            let index_file = read_unsigned(&mut data)? as usize;
+            let position = Position {
+                line: read_unsigned(&mut data)? as usize,
+                column: read_unsigned(&mut data)? as usize,
+            };
+            let pointer = read_signed(&mut data)? as isize;
+            let changes = (0..read_unsigned(&mut data)?)
+                .map(|_| {
+                    let position = read_signed(&mut data)? as isize;
+                    let len = read_unsigned(&mut data)? as usize;
+                    let value = data.get(..len).ok_or_else(truncated)?;
+                    data = &data[len..];
+                    Ok((position, BigInt::from_signed_bytes_le(value)))
+                })
+                .collect::<Result<Vec<(isize, BigInt)>, InterpreterError>>()?;
+            steps.push(TraceStep {
+                token,
+                index_file,
+                position,
+                pointer,
+                changes,
+            });
+        }
+
+        Ok(Self { source, steps })
+    }
+
+    // Pointer and memory cells written after the first `steps` steps, the other cells are 0
+    pub fn state(&self, steps: usize) -> (isize, BTreeMap<isize, BigInt>) {
+        let mut pointer = 0;
+        let mut cells = BTreeMap::new();
+        for step in self.steps.iter().take(steps) {
+            pointer = step.pointer;
+            cells.extend(step.changes.iter().cloned());
+        }
+        cells.retain(|_, value| *value != BigInt::default());
+        (pointer, cells)
+    }
+}
+
+fn truncated() -> InterpreterError {
+    InterpreterError::InvalidTrace("truncated step".to_string())
+}
+
+// LEB128: 7 bits per byte, the high bit is set when more bytes follow
+fn write_unsigned(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+// Zigzag encoding, small negative numbers are short too
+fn write_signed(bytes: &mut Vec<u8>, value: i64) {
+    write_unsigned(bytes, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn read_unsigned(data: &mut &[u8]) -> Result<u64, InterpreterError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = data.split_first().ok_or_else(truncated)?;
+        *data = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(InterpreterError::InvalidTrace(
+        "integer too long".to_string(),
+    ))
+}
+
+fn read_signed(data: &mut &[u8]) -> Result<i64, InterpreterError> {
+    let value = read_unsigned(data)?;
+    Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brainfuck_interpreter::{
+        builder::InterpreterBuilder, optimizer::OptLevel, tape::TapePolicy,
+    };
+    use std::{cell::RefCell, rc::Rc};
+
+    // Writer whose bytes are still readable after the tracer takes it
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Trace of `code` run without optimizations on a tape that grows to both sides
+    fn record(code: &str) -> Vec<u8> {
+        let buffer = Shared::default();
+        let source = SourceMap::new("<inline>", code);
+        let builder = InterpreterBuilder::new()
+            .opt_level(OptLevel::O0)
+            .tape(TapePolicy::GrowBoth)
+            .source(source.clone());
+        let tokens = builder.compile(code).unwrap();
+        let mut bf = builder.build();
+        bf.set_tracer(Some(
+            Tracer::new(Box::new(buffer.clone()), &source).unwrap(),
+        ));
+        bf.execute(tokens, None).unwrap();
+        let bytes = buffer.0.borrow().clone();
+        bytes
+    }
+
+    #[test]
+    fn integers_round_trip() {
+        let unsigned = [0, 1, 127, 128, 300, u64::MAX];
+        let signed = [0, -1, 1, -64, 64, i64::MIN, i64::MAX];
+        let mut bytes = Vec::new();
+        for value in unsigned {
+            write_unsigned(&mut bytes, value);
+        }
+        for value in signed {
+            write_signed(&mut bytes, value);
+        }
+        let mut data = bytes.as_slice();
+        for value in unsigned {
+            assert_eq!(read_unsigned(&mut data).unwrap(), value);
+        }
+        for value in signed {
+            assert_eq!(read_signed(&mut data).unwrap(), value);
+        }
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn small_integers_are_short() {
+        let encode = |write: &dyn Fn(&mut Vec<u8>)| {
+            let mut bytes = Vec::new();
+            write(&mut bytes);
+            bytes
+        };
+        assert_eq!(encode(&|bytes| write_unsigned(bytes, 127)), [0x7f]);
+        assert_eq!(encode(&|bytes| write_unsigned(bytes, 128)), [0x80, 0x01]);
+        assert_eq!(encode(&|bytes| write_unsigned(bytes, 300)), [0xac, 0x02]);
+        assert_eq!(encode(&|bytes| write_unsigned(bytes, u64::MAX)).len(), 10);
+        // Zigzag: 0, -1, 1, -2... are 0, 1, 2, 3...
+        assert_eq!(encode(&|bytes| write_signed(bytes, -1)), [0x01]);
+        assert_eq!(encode(&|bytes| write_signed(bytes, 1)), [0x02]);
+        assert_eq!(encode(&|bytes| write_signed(bytes, -64)), [0x7f]);
+        assert_eq!(encode(&|bytes| write_signed(bytes, 64)), [0x80, 0x01]);
+    }
+
+    #[test]
+    fn recorded_runs_read_back() {
+        // The last commands are after the 128th character and the pointer ends left of the first cell
+        let code = format!("{}{}-", ">".repeat(130), "<".repeat(200));
+        let trace = Trace::read(&mut record(&code).as_slice()).unwrap();
+        assert_eq!(trace.source, "<inline>");
+        assert_eq!(trace.steps.len(), 331);
+        assert_eq!(
+            trace.steps.last(),
+            Some(&TraceStep {
+                token: 330,
+                index_file: 331,
+                position: Position {
+                    line: 1,
+                    column: 331
+                },
+                pointer: -70,
+                changes: vec![(-70, BigInt::from(255))],
+            })
+        );
+        assert_eq!(trace.steps[129].pointer, 130);
+        let (pointer, cells) = trace.state(331);
+        assert_eq!(pointer, -70);
+        assert_eq!(
+            cells.into_iter().collect::<Vec<_>>(),
+            [(-70, BigInt::from(255))]
+        );
+        assert_eq!(trace.state(330), (-70, BTreeMap::new()));
+    }
+
+    #[test]
+    fn truncated_traces_are_invalid() {
+        let bytes = record("+>-");
+        let invalid = |bytes: &[u8]| match Trace::read(&mut &bytes[..]) {
+            Err(InterpreterError::InvalidTrace(reason)) => reason,
+            result => panic!("{:?}", result),
+        };
+        assert_eq!(invalid(&bytes[..5]), "missing header");
+        assert_eq!(invalid(&bytes[..bytes.len() - 1]), "truncated step");
+        // 255 is written in two bytes, a value shorter than its length is truncated too
+        assert_eq!(invalid(&bytes[..bytes.len() - 2]), "truncated step");
+        let mut bytes = bytes;
+        bytes.extend_from_slice(&[0xff; 10]);
+        assert_eq!(invalid(&bytes), "integer too long");
+        bytes[MAGIC.len()] = VERSION + 1;
+        assert_eq!(invalid(&bytes), "unknown version");
+    }
+}
@@ -0,0 +1,296 @@
+use brainfuck::{
+    CellWidth, Config, Data, EofPolicy, InterpreterError, OptLevel, OverflowPolicy, ParseMode,
+    Pass, TapePolicy,
+};
+use std::{io::Read, str::FromStr, time::Duration};
+
+const FUZZ_SEED: u64 = 1;
+const FUZZ_ITERATIONS: u64 = 10_000;
+
+pub const DEBUG_USAGE: &str = "Debugger commands:\n  s, step [n]        Execute the next n instructions (default 1)\n  c, continue        Run until a breakpoint, a watchpoint or the end of the program\n  b, break <pos>     Stop before the instruction at source position pos\n  d, delete <pos>    Remove the breakpoint at pos\n  w, watch <cell>    Stop when the value of the memory cell changes\n  u, unwatch <cell>  Remove the watchpoint of the memory cell\n  t, tape [radius]   Print the memory cells around the pointer (default 8)\n  i, info            Print the next instruction, the pointer, the breakpoints and the watchpoints\n  q, quit            Exit the debugger";
+
+pub const USAGE: &str = "./brainfuck <bf_code> [bf_args]\n       ./brainfuck <command> [options] <source> [bf_args]\n       ./brainfuck fuzz [--iterations <n>] [--seed <n>]\n       ./brainfuck replay <trace> [step]\n\nBrainfuck interpreter.\n\nCommands:\n  run              Execute the program and print its output\n  check            Parse the program and report errors without executing it\n  dump-ir          Print the optimized instructions generated from the program\n  emit-c           Print a C program equivalent to the optimized instructions (no bignum cells, step or time limits)\n  emit-rust        Print a Rust function `run(input, output)` equivalent to the optimized instructions (same limits as emit-c)\n  debug            Run the program step by step with commands read from stdin (the input of the program comes from bf_args)\n  replay           Print the steps recorded with --trace, or the memory after the given step\n  fuzz             Compare the optimized interpreter with the reference one using random programs (default 10000 iterations)\n\nOptions:\n  --strict         Any character other than the 8 instructions is an error (default for inline code)\n  --lenient        Any character other than the 8 instructions is a comment (default for files and stdin)\n  --interactive    Stream the output while running and read the input from stdin when there are no bf_args\n  --max-steps <n>  Stop the program after executing n instructions\n  --timeout <ms>   Stop the program after the given milliseconds\n  --eof <policy>   Value read after the end of the input: unchanged, zero, max (-1), error or zero-then-error (default)\n  --cell <bits>    Size of the memory cells: 8 (default), 16, 32 or bignum\n  --overflow <policy>  When a cell goes past its limits: wrap (default) or error\n  --checked        Same as `--overflow error --tape error`\n  --tape-size <n>  Number of memory cells at the start (default 65536)\n  --tape <policy>  When the pointer leaves the tape: wrap (default), error, grow-right or grow-both\n  -O<level>        Optimization level: 0 (no rewrites), 1 (fusion and loops) or 2 (every pass, default)\n  --enable <pass>  Enable an optimization pass: fusion, loops, dead or fold\n  --disable <pass> Disable an optimization pass\n  --stats          Print to stderr the instructions removed by each optimization pass\n  --debug-dump     `#` is an instruction that prints the memory cells around the pointer to stderr\n  --profile        Print to stderr the executions and time of every loop and the most expensive instructions\n  --trace <file>   Record every instruction executed, its position, the pointer and the memory cells written\n  --no-jit         Use the interpreter even when the program can be compiled to machine code (`jit` feature)\n\nArguments:\n  <bf_code>        Brainfuck code to be executed. Use only the following 8 instructions: +-.,[]<>\n  <source>         Path of the file with the Brainfuck code, `-` to read it from stdin or `-e <bf_code>` for inline code.\n  [bf_args]        Pass a single string parameter to be converted into a collection of u8 characters (ascii).\n                   Pass a collection of u8 numbers (0 to 255).";
+
+// Where the Brainfuck code is read from
+pub enum Source {
+    Inline(String),
+    File(String),
+    Stdin,
+}
+
+// Action requested from the command line
+#[derive(PartialEq)]
+pub enum Action {
+    Run,
+    Check,
+    DumpIr,
+    EmitC,
+    EmitRust,
+    Debug,
+    Fuzz { seed: u64, iterations: u64 },
+    Replay { step: Option<usize> },
+}
+
+// Parsed command line arguments
+pub struct Cli {
+    pub action: Action,
+    pub source: Source,
+    pub mode: Option<ParseMode>,
+    pub interactive: bool,
+    pub stats: bool,
+    pub profile: bool,
+    pub trace: Option<String>, // file where the executed commands are recorded
+    pub config: Config,
+    pub args: Option<Data>,
+}
+
+impl Cli {
+    pub fn parse(args: &[String]) -> Result<Self, InterpreterError> {
+        let action = match args.get(1).map(String::as_str) {
+            Some("run") => Action::Run,
+            Some("check") => Action::Check,
+            Some("dump-ir") => Action::DumpIr,
+            Some("emit-c") => Action::EmitC,
+            Some("emit-rust") => Action::EmitRust,
+            Some("debug") => Action::Debug,
+            Some("fuzz") => return Self::parse_fuzz(args),
+            Some("replay") => return Self::parse_replay(args),
+            // Legacy form: `./brainfuck <bf_code> [bf_args]`
+            Some(code) => {
+                return Ok(Self {
+                    action: Action::Run,
+                    source: Source::Inline(code.to_string()),
+                    mode: None,
+                    interactive: false,
+                    stats: false,
+                    profile: false,
+                    trace: None,
+                    config: Config::default(),
+                    args: Self::parse_args(&args[2..])?,
+                })
+            }
+            None => return Err(InterpreterError::SintaxisError(USAGE.to_string())),
+        };
+
+        let mut index = 2;
+        let mut mode = None;
+        let mut interactive = false;
+        let mut stats = false;
+        let mut profile = false;
+        let mut trace = None;
+        let mut config = Config::default();
+        while let Some(option) = args
+            .get(index)
+            .filter(|arg| arg.starts_with("--") || arg.starts_with("-O"))
+        {
+            match option.as_str() {
+                "--strict" => mode = Some(ParseMode::Strict),
+                "--lenient" => mode = Some(ParseMode::Lenient),
+                "--interactive" => interactive = true,
+                "--stats" => stats = true,
+                "--profile" => profile = true,
+                "--no-jit" => config.jit = false,
+                "--debug-dump" => config.debug_dump = true,
+                "--trace" => {
+                    trace = Some(Self::option_value(args, index)?.to_string());
+                    index += 1;
+                }
+                level if level.starts_with("-O") => {
+                    config.passes = level[2..].parse::<OptLevel>()?.passes();
+                }
+                "--enable" | "--disable" => {
+                    let pass = Self::option_parse::<Pass>(args, index)?;
+                    config.set_pass(pass, option == "--enable");
+                    index += 1;
+                }
+                "--cell" => {
+                    config.cell = Self::option_value(args, index)?.parse::<CellWidth>()?;
+                    index += 1;
+                }
+                "--overflow" => {
+                    config.overflow = Self::option_value(args, index)?.parse::<OverflowPolicy>()?;
+                    index += 1;
+                }
+                "--checked" => {
+                    config.overflow = OverflowPolicy::Error;
+                    config.tape = TapePolicy::Error;
+                }
+                "--tape" => {
+                    config.tape = Self::option_value(args, index)?.parse::<TapePolicy>()?;
+                    index += 1;
+                }
+                "--tape-size" => {
+                    let value = Self::option_value(args, index)?;
+                    config.tape_size = match value.parse::<usize>() {
+                        Ok(size) if size > 0 => size,
+                        _ => {
+                            return Err(InterpreterError::InvalidOption(
+                                option.to_string(),
+                                value.to_string(),
+                            ))
+                        }
+                    };
+                    index += 1;
+                }
+                "--max-steps" => {
+                    config.max_steps = Some(Self::option_parse(args, index)?);
+                    index += 1;
+                }
+                "--timeout" => {
+                    config.timeout = Some(Duration::from_millis(Self::option_parse(args, index)?));
+                    index += 1;
+                }
+                "--eof" => {
+                    config.eof = Self::option_value(args, index)?.parse::<EofPolicy>()?;
+                    index += 1;
+                }
+                _ => return Err(InterpreterError::SintaxisError(USAGE.to_string())),
+            }
+            index += 1;
+        }
+
+        let (source, rest) = match args.get(index).map(String::as_str) {
+            Some("-e") => match args.get(index + 1) {
+                Some(code) => (Source::Inline(code.to_string()), &args[index + 2..]),
+                None => return Err(InterpreterError::SintaxisError(USAGE.to_string())),
+            },
+            Some("-") => (Source::Stdin, &args[index + 1..]),
+            Some(path) => (Source::File(path.to_string()), &args[index + 1..]),
+            None => return Err(InterpreterError::SintaxisError(USAGE.to_string())),
+        };
+
+        Ok(Self {
+            action,
+            source,
+            mode,
+            interactive,
+            stats,
+            profile,
+            trace,
+            config,
+            args: Self::parse_args(rest)?,
+        })
+    }
+
+    // `./brainfuck fuzz [--iterations n] [--seed n]`, it does not have source code
+    fn parse_fuzz(args: &[String]) -> Result<Self, InterpreterError> {
+        let (mut seed, mut iterations) = (FUZZ_SEED, FUZZ_ITERATIONS);
+        let mut index = 2;
+        while let Some(option) = args.get(index) {
+            match option.as_str() {
+                "--seed" => seed = Self::option_parse(args, index)?,
+                "--iterations" => iterations = Self::option_parse(args, index)?,
+                _ => return Err(InterpreterError::SintaxisError(USAGE.to_string())),
+            }
+            index += 2;
+        }
+
+        Ok(Self {
+            action: Action::Fuzz { seed, iterations },
+            source: Source::Inline(String::new()),
+            mode: None,
+            interactive: false,
+            stats: false,
+            profile: false,
+            trace: None,
+            config: Config::default(),
+            args: None,
+        })
+    }
+
+    // `./brainfuck replay <trace> [step]`, the source is the trace file
+    fn parse_replay(args: &[String]) -> Result<Self, InterpreterError> {
+        let (path, step) = match &args[2..] {
+            [path] => (path, None),
+            [path, step] => match step.parse() {
+                Ok(step) => (path, Some(step)),
+                Err(_) => {
+                    return Err(InterpreterError::InvalidOption(
+                        "replay".to_string(),
+                        step.to_string(),
+                    ))
+                }
+            },
+            _ => return Err(InterpreterError::SintaxisError(USAGE.to_string())),
+        };
+
+        Ok(Self {
+            action: Action::Replay { step },
+            source: Source::File(path.to_string()),
+            mode: None,
+            interactive: false,
+            stats: false,
+            profile: false,
+            trace: None,
+            config: Config::default(),
+            args: None,
+        })
+    }
+
+    // Value of the option at `index`
+    fn option_value(args: &[String], index: usize) -> Result<&str, InterpreterError> {
+        match args.get(index + 1) {
+            Some(value) => Ok(value),
+            None => Err(InterpreterError::SintaxisError(USAGE.to_string())),
+        }
+    }
+
+    // Value of the option at `index` converted to `T`
+    fn option_parse<T: FromStr>(args: &[String], index: usize) -> Result<T, InterpreterError> {
+        let value = Self::option_value(args, index)?;
+        value.parse().map_err(|_| {
+            InterpreterError::InvalidOption(args[index].to_string(), value.to_string())
+        })
+    }
+
+    // Convert the Brainfuck arguments into the input buffer
+    fn parse_args(args: &[String]) -> Result<Option<Data>, InterpreterError> {
+        match args.len() {
+            0 => Ok(None),
+            1 => Ok(Some(args[0].as_bytes().to_vec())),
+            _ => {
+                let args = args
+                    .iter()
+                    .map(|arg| {
+                        arg.parse()
+                            .map_err(|e| InterpreterError::ParseError(arg.to_string(), e))
+                    })
+                    .collect::<Result<Data, InterpreterError>>()?;
+                Ok(Some(args))
+            }
+        }
+    }
+
+    // Inline code keeps the strict parsing, files and stdin usually contain comments
+    pub fn parse_mode(&self) -> ParseMode {
+        match (self.mode, &self.source) {
+            (Some(mode), _) => mode,
+            (None, Source::Inline(_)) => ParseMode::Strict,
+            (None, _) => ParseMode::Lenient,
+        }
+    }
+
+    // Name of the source in the positions of the errors
+    pub fn source_name(&self) -> String {
+        match &self.source {
+            Source::Inline(_) => "<inline>".to_string(),
+            Source::File(path) => path.to_string(),
+            Source::Stdin => "<stdin>".to_string(),
+        }
+    }
+
+    pub fn read_code(&self) -> Result<String, InterpreterError> {
+        match &self.source {
+            Source::Inline(code) => Ok(code.to_string()),
+            Source::File(path) => std::fs::read_to_string(path)
+                .map_err(|e| InterpreterError::ReadError(path.to_string(), e)),
+            Source::Stdin => {
+                let mut code = String::new();
+                std::io::stdin()
+                    .read_to_string(&mut code)
+                    .map_err(|e| InterpreterError::ReadError("<stdin>".to_string(), e))?;
+                Ok(code)
+            }
+        }
+    }
+}
@@ -0,0 +1,21 @@
+// Brainfuck interpreter library: tokenizer and optimizer (`Command`), virtual machine (`Interpreter`), step debugger (`Debugger`) and C and Rust backends (`emit_c`, `emit_rust`)
+pub use brainfuck_interpreter::{
+    builder::InterpreterBuilder,
+    c_backend::emit_c,
+    cell::{Cell, CellWidth},
+    command::{BufferOptions, Command, Commands, LoopOptions, ParseMode},
+    config::{Config, EofPolicy, OverflowPolicy},
+    debugger::{Debugger, Stop},
+    error::InterpreterError,
+    fuzz::{fuzz, FuzzFailure, FuzzReport},
+    interpreter::{Data, Interpreter, Status},
+    optimizer::{OptLevel, Pass, PassStats},
+    oracle::{differential_test, run_optimized, run_reference, Outcome, Verdict},
+    profiler::{CommandProfile, LoopProfile, Profile, Profiler},
+    rust_backend::emit_rust,
+    source::{Position, SourceMap},
+    tape::TapePolicy,
+    trace::{Trace, TraceStep, Tracer},
+};
+
+mod brainfuck_interpreter;
@@ -1,12 +1,359 @@
-use brainfuck_interpreter::brainfuck_interpreter;
+use brainfuck::{
+    emit_c, emit_rust, fuzz, Command, Commands, Config, Data, Debugger, Interpreter,
+    InterpreterError, Position, Profiler, SourceMap, Stop, Trace, Tracer,
+};
+use cli::{Action, Cli, Source, DEBUG_USAGE, USAGE};
+use std::{
+    io::{BufRead, Read, Write},
+    process::ExitCode,
+    str::FromStr,
+};
 
-mod brainfuck_interpreter;
+mod cli;
+
+// Function to interpret Brainfuck code from command line arguments
+fn brainfuck_interpreter() -> Result<Option<String>, InterpreterError> {
+    // Get command line arguments
+    let args: Vec<String> = std::env::args().collect();
+    let cli = Cli::parse(&args)?;
+    if let Action::Fuzz { seed, iterations } = cli.action {
+        let report = fuzz(seed, iterations);
+        let mut lines = vec![format!(
+            "Programs: {}, inconclusive: {}, mismatches: {}",
+            report.programs,
+            report.inconclusive,
+            report.failures.len()
+        )];
+        for failure in report.failures {
+            lines.push(format!(
+                "\"{}\" {:?} {:?}\n    {}",
+                failure.code, failure.args, failure.config, failure.description
+            ));
+        }
+        return Ok(Some(lines.join("\n")));
+    }
+    if let Action::Replay { step } = cli.action {
+        return replay(&cli, step);
+    }
+    let code = cli.read_code()?;
+    let source = SourceMap::new(&cli.source_name(), &code);
+    run_program(cli, code, &source).map_err(|error| source.locate(error))
+}
+
+// Parse the source code and run the action, the errors have the position in `source`
+fn run_program(
+    cli: Cli,
+    code: String,
+    source: &SourceMap,
+) -> Result<Option<String>, InterpreterError> {
+    let (tokens, stats) = Command::code_to_tokens_with_stats(code, cli.parse_mode(), &cli.config)?;
+    if cli.stats {
+        for pass in stats {
+            eprintln!("{}", pass);
+        }
+    }
+
+    match cli.action {
+        Action::Check => Ok(Some(format!("OK: {} instructions", tokens.len()))),
+        Action::DumpIr => Ok(Some(
+            tokens
+                .iter()
+                .enumerate()
+                .map(|(index, token)| format!("{:>6}  {:?}", index, token))
+                .collect::<Vec<String>>()
+                .join("\n"),
+        )),
+        Action::EmitC => {
+            print!("{}", emit_c(&tokens, &cli.config)?);
+            Ok(None)
+        }
+        Action::EmitRust => {
+            print!("{}", emit_rust(&tokens, &cli.config)?);
+            Ok(None)
+        }
+        Action::Debug => debug(tokens, cli.config, cli.args, source),
+        Action::Run if cli.interactive => {
+            // The output is written to stdout while the program runs
+            let mut bf = Interpreter::new(cli.config);
+            bf.set_source(Some(source.clone()));
+            bf.set_tracer(tracer(&cli.trace, source)?);
+            bf.set_profiler(cli.profile.then(Profiler::new));
+            let mut output = std::io::stdout().lock();
+            let result = match cli.args {
+                Some(args) => bf.execute_io(tokens, &mut args.as_slice(), &mut output),
+                None => bf.execute_io(tokens, &mut std::io::stdin().lock(), &mut output),
+            };
+            print_profile(&bf, source);
+            result?;
+
+            Ok(None)
+        }
+        Action::Run | Action::Fuzz { .. } | Action::Replay { .. } => {
+            // Create a new Brainfuck instance and execute the code
+            let mut bf = Interpreter::new(cli.config);
+            bf.set_source(Some(source.clone()));
+            bf.set_tracer(tracer(&cli.trace, source)?);
+            bf.set_profiler(cli.profile.then(Profiler::new));
+            let result = bf.execute(tokens, cli.args);
+            print_profile(&bf, source);
+            result?;
+
+            // Return the output as String and Vec<u8>
+            Ok(Some(format!(
+                "\"{}\" {:?}",
+                bf.get_output_as_string()?,
+                bf.get_output_as_vec()?
+            )))
+        }
+    }
+}
+
+// Report of `--profile`, also when the program fails
+fn print_profile(bf: &Interpreter, source: &SourceMap) {
+    if let Some(profile) = bf.profile(source) {
+        eprintln!("{}", profile);
+    }
+}
+
+// Trace written to the file of `--trace`
+fn tracer(path: &Option<String>, source: &SourceMap) -> Result<Option<Tracer>, InterpreterError> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let file = std::fs::File::create(path)
+        .map_err(|e| InterpreterError::TraceWriteError(path.to_string(), e))?;
+    Ok(Some(Tracer::new(
+        Box::new(std::io::BufWriter::new(file)),
+        source,
+    )?))
+}
+
+// Every step of a trace, or the memory after `step` steps
+fn replay(cli: &Cli, step: Option<usize>) -> Result<Option<String>, InterpreterError> {
+    let Source::File(path) = &cli.source else {
+        return Err(InterpreterError::SintaxisError(USAGE.to_string()));
+    };
+    let mut file =
+        std::fs::File::open(path).map_err(|e| InterpreterError::ReadError(path.to_string(), e))?;
+    let trace = Trace::read(&mut file)?;
+    let Some(step) = step else {
+        let lines: Vec<String> = trace
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| {
+                let changes: Vec<String> = step
+                    .changes
+                    .iter()
+                    .map(|(cell, value)| format!("  {}={}", cell, value))
+                    .collect();
+                format!(
+                    "{:>8}  instruction {:>6} at {:>9}  pointer {:>6}{}",
+                    index + 1,
+                    step.token,
+                    step.position.to_string(),
+                    step.pointer,
+                    changes.concat()
+                )
+            })
+            .collect();
+        return Ok(Some(lines.join("\n")));
+    };
+
+    if step > trace.steps.len() {
+        return Err(InterpreterError::InvalidOption(
+            "replay".to_string(),
+            step.to_string(),
+        ));
+    }
+    let (pointer, cells) = trace.state(step);
+    let mut lines = vec![match step.checked_sub(1).map(|index| &trace.steps[index]) {
+        Some(last) => format!(
+            "After step {} of {}: instruction {} at {}:{}",
+            step,
+            trace.steps.len(),
+            last.token,
+            trace.source,
+            last.position
+        ),
+        None => format!("Before the first step of {}", trace.steps.len()),
+    }];
+    lines.push(format!("Pointer at memory cell {}", pointer));
+    lines.extend(
+        cells
+            .iter()
+            .map(|(cell, value)| format!("{:>7}  {}", cell, value)),
+    );
+    Ok(Some(lines.join("\n")))
+}
+
+// Debugger commands are read from stdin, the program reads its input from `bf_args`
+fn debug(
+    tokens: Commands,
+    config: Config,
+    args: Option<Data>,
+    source: &SourceMap,
+) -> Result<Option<String>, InterpreterError> {
+    let mut bf = Interpreter::new(config);
+    bf.set_source(Some(source.clone()));
+    bf.load(tokens);
+    let mut debugger = Debugger::new(bf);
+    let mut input = args.as_deref();
+    let mut output = std::io::stdout();
+    println!("{}", debug_state(&debugger, source));
+
+    for line in std::io::stdin().lock().lines() {
+        let line = line.map_err(InterpreterError::IoError)?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if let ["q" | "quit"] = words.as_slice() {
+            break;
+        }
+        let input = input.as_mut().map(|input| input as &mut dyn Read);
+        let mut produced = Data::new();
+        let result = debug_command(&mut debugger, &words, input, &mut produced, source);
+        // The output of the program is shown on its own lines before the message
+        if !produced.is_empty() {
+            output
+                .write_all(&produced)
+                .map_err(InterpreterError::IoError)?;
+            if !produced.ends_with(b"\n") {
+                println!();
+            }
+        }
+        match result {
+            Ok(message) => println!("{}", message),
+            Err(error) => println!("{}", source.locate(error)),
+        }
+    }
+    Ok(None)
+}
+
+// Run a debugger command, the result is the message to show
+fn debug_command(
+    debugger: &mut Debugger,
+    words: &[&str],
+    input: Option<&mut dyn Read>,
+    output: &mut dyn Write,
+    source: &SourceMap,
+) -> Result<String, InterpreterError> {
+    let stop = match words {
+        ["s" | "step"] => debugger.step(1, input, output)?,
+        ["s" | "step", steps] => debugger.step(debug_number(words[0], steps)?, input, output)?,
+        ["c" | "continue"] => debugger.resume(input, output)?,
+        ["b" | "break", position] => {
+            debugger.add_breakpoint(debug_position(words[0], position, source)?)?;
+            return Ok(format!("Breakpoints: {:?}", debugger.breakpoints()));
+        }
+        ["d" | "delete", position] => {
+            debugger.remove_breakpoint(debug_position(words[0], position, source)?);
+            return Ok(format!("Breakpoints: {:?}", debugger.breakpoints()));
+        }
+        ["w" | "watch", cell] => {
+            debugger.add_watchpoint(debug_number(words[0], cell)?);
+            return Ok(format!("Watchpoints: {:?}", debugger.watchpoints()));
+        }
+        ["u" | "unwatch", cell] => {
+            debugger.remove_watchpoint(debug_number(words[0], cell)?);
+            return Ok(format!("Watchpoints: {:?}", debugger.watchpoints()));
+        }
+        ["t" | "tape"] => return Ok(debug_tape(debugger, 8)),
+        ["t" | "tape", radius] => return Ok(debug_tape(debugger, debug_number(words[0], radius)?)),
+        ["i" | "info"] => {
+            return Ok(format!(
+                "{}\nBreakpoints: {:?}\nWatchpoints: {:?}",
+                debug_state(debugger, source),
+                debugger.breakpoints(),
+                debugger.watchpoints()
+            ))
+        }
+        _ => return Ok(DEBUG_USAGE.to_string()),
+    };
+
+    Ok(match stop {
+        Stop::Stepped => debug_state(debugger, source),
+        Stop::Breakpoint(position) => format!(
+            "Breakpoint at {}\n{}",
+            source.location(position),
+            debug_state(debugger, source)
+        ),
+        Stop::Watchpoint(cell, previous, value) => format!(
+            "Memory cell {} changed from {} to {}\n{}",
+            cell,
+            previous,
+            value,
+            debug_state(debugger, source)
+        ),
+        Stop::Halted => debug_state(debugger, source),
+    })
+}
+
+fn debug_number<T: FromStr>(command: &str, value: &str) -> Result<T, InterpreterError> {
+    value
+        .parse()
+        .map_err(|_| InterpreterError::InvalidOption(command.to_string(), value.to_string()))
+}
+
+// Source position as `index_file` or `line:column`
+fn debug_position(
+    command: &str,
+    value: &str,
+    source: &SourceMap,
+) -> Result<usize, InterpreterError> {
+    if !value.contains(':') {
+        return debug_number(command, value);
+    }
+    debug_number::<Position>(command, value)
+        .ok()
+        .and_then(|position| source.index_file(position))
+        .ok_or_else(|| InterpreterError::InvalidOption(command.to_string(), value.to_string()))
+}
+
+// Next instruction and current memory cell
+fn debug_state(debugger: &Debugger, source: &SourceMap) -> String {
+    let bf = debugger.interpreter();
+    match bf.current_command() {
+        Some(command) => format!(
+            "Step {}: instruction {} `{:?}` at {} (position {}), pointer at memory cell {} = {}",
+            bf.steps(),
+            bf.token_index(),
+            command,
+            source.location(command.index_file()),
+            command.index_file(),
+            bf.pointer(),
+            bf.cell(bf.pointer()).unwrap_or_default()
+        ),
+        None => format!("Program finished after {} steps", bf.steps()),
+    }
+}
+
+// Memory cells around the pointer, the pointer is marked with `>`
+fn debug_tape(debugger: &Debugger, radius: usize) -> String {
+    let pointer = debugger.interpreter().pointer();
+    debugger
+        .interpreter()
+        .tape_window(radius)
+        .into_iter()
+        .map(|(cell, value)| {
+            let mark = if cell == pointer { '>' } else { ' ' };
+            format!("{}{:>6}  {}", mark, cell, value)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
 
 // Main function to run the brainfuck interpreter
-fn main() {
+fn main() -> ExitCode {
     // Match the result of the Brainfuck interpreter function and print the output or error
     match brainfuck_interpreter() {
-        Ok(output) => println!("\"{}\" {:?}", output.0, output.1),
-        Err(error) => eprintln!("{}", error),
+        Ok(output) => {
+            if let Some(output) = output {
+                println!("{}", output);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
     }
 }